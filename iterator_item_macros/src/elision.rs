@@ -1,4 +1,5 @@
 use proc_macro2::Span;
+use quote::ToTokens;
 use syn::fold::Fold;
 use syn::punctuated::Punctuated;
 use syn::token::Comma;
@@ -14,6 +15,210 @@ pub fn unelide_lifetimes(
         .collect()
 }
 
+pub fn unelide_type_lifetimes(generics: &mut Punctuated<GenericParam, Comma>, ty: Type) -> Type {
+    UnelideLifetimes::new(generics).fold_type(ty)
+}
+
+/// Add a lifetime parameter to `generics` that all of `lifetimes` outlive, for a trait object that
+/// borrows from all of them: `dyn Trait + 'a + 'b` isn't allowed.
+pub fn outlived_lifetime(generics: &mut Generics, lifetimes: &[Lifetime]) -> Lifetime {
+    let name = format!("{}it", lifetime_name(&generics.params));
+    let lifetime = Lifetime::new(&name, Span::call_site());
+    let index = lifetime_index(&generics.params);
+    generics.params.insert(
+        index,
+        GenericParam::Lifetime(LifetimeDef::new(lifetime.clone())),
+    );
+    let where_clause = generics.make_where_clause();
+    for outliving in lifetimes {
+        where_clause
+            .predicates
+            .push(parse_quote!(#outliving: #lifetime));
+    }
+    lifetime
+}
+
+/// Name the elided lifetimes in `ty`, a type the iterator item yields or returns, like `rustc`
+/// does for the return type of regular functions: they are the lifetime of `&self`, or of the
+/// `&Self` in a typed receiver, if there is one, otherwise the only lifetime in `args`. `args` must
+/// already be unelided.
+pub fn unelide_output_lifetimes(args: &Punctuated<FnArg, Comma>, ty: Type) -> Type {
+    UnelideOutput {
+        lifetime: output_lifetime(args),
+        reported: false,
+    }
+    .fold_type(ty)
+}
+
+/// The lifetimes that `ty`, the `impl Trait` return type of a `#[iterator]` function, captures:
+/// the ones it names, and the one its elided lifetimes stand for. `args` must already be unelided.
+pub fn captured_lifetimes(args: &Punctuated<FnArg, Comma>, ty: Type) -> Vec<Lifetime> {
+    // When they are ambiguous, `rustc` reports it on the return type, which is left as written.
+    let ty = UnelideOutput {
+        lifetime: output_lifetime(args),
+        reported: true,
+    }
+    .fold_type(ty);
+    let mut collector = CollectLifetimes(vec![]);
+    collector.fold_type(ty);
+    collector.0
+}
+
+/// The lifetime elided lifetimes in output types stand for, or the arguments with lifetimes if
+/// there isn't exactly one candidate.
+fn output_lifetime(args: &Punctuated<FnArg, Comma>) -> std::result::Result<Lifetime, Vec<String>> {
+    let mut lifetimes = vec![];
+    let mut borrowing = vec![];
+    for arg in args {
+        match arg {
+            FnArg::Receiver(Receiver {
+                reference: Some((_, Some(lifetime))),
+                ..
+            }) => return Ok(lifetime.clone()),
+            FnArg::Receiver(_) => {}
+            FnArg::Typed(arg @ PatType { pat, ty, .. }) => {
+                // Like `rustc`, a typed receiver with a reference to `Self` in it, like
+                // `self: &Self` or `self: Pin<&mut Self>`, lends its lifetime like `&self` does.
+                if is_receiver(arg) {
+                    let mut finder = SelfReference(None);
+                    finder.fold_type((**ty).clone());
+                    if let Some(lifetime) = finder.0 {
+                        return Ok(lifetime);
+                    }
+                }
+                let mut collector = CollectLifetimes(vec![]);
+                collector.fold_type((**ty).clone());
+                if !collector.0.is_empty() {
+                    borrowing.push(pat.to_token_stream().to_string());
+                }
+                for lifetime in collector.0 {
+                    if !lifetimes.contains(&lifetime) {
+                        lifetimes.push(lifetime);
+                    }
+                }
+            }
+        }
+    }
+    match lifetimes.len() {
+        1 => Ok(lifetimes.remove(0)),
+        _ => Err(borrowing),
+    }
+}
+
+/// Whether `arg` is a receiver with an explicit type, like `self: Box<Self>`. `syn` parses those
+/// as regular arguments.
+pub fn is_receiver(arg: &PatType) -> bool {
+    matches!(&*arg.pat, Pat::Ident(PatIdent { ident, .. }) if ident == "self")
+}
+
+/// The lifetime of the first reference to `Self` in the types it folds.
+struct SelfReference(Option<Lifetime>);
+
+impl Fold for SelfReference {
+    fn fold_type_reference(&mut self, ty_ref: TypeReference) -> TypeReference {
+        let is_self = matches!(&*ty_ref.elem, Type::Path(TypePath { qself: None, path }) if path.is_ident("Self"));
+        if is_self && self.0.is_none() {
+            self.0 = ty_ref.lifetime.clone();
+        }
+        fold::fold_type_reference(self, ty_ref)
+    }
+}
+
+/// Every lifetime in the types it folds, which it leaves as they are.
+struct CollectLifetimes(Vec<Lifetime>);
+
+impl Fold for CollectLifetimes {
+    fn fold_lifetime(&mut self, lifetime: Lifetime) -> Lifetime {
+        self.0.push(lifetime.clone());
+        lifetime
+    }
+
+    // Like `rustc`, we don't count the lifetimes of `fn` pointers and `Fn` traits.
+    fn fold_type_bare_fn(&mut self, ty: TypeBareFn) -> TypeBareFn {
+        ty
+    }
+
+    fn fold_parenthesized_generic_arguments(
+        &mut self,
+        args: ParenthesizedGenericArguments,
+    ) -> ParenthesizedGenericArguments {
+        args
+    }
+}
+
+struct UnelideOutput {
+    lifetime: std::result::Result<Lifetime, Vec<String>>,
+    /// Whether we already complained about an elided lifetime we can't name, once is enough.
+    reported: bool,
+}
+
+impl UnelideOutput {
+    /// The lifetime for an elided one at `span`, or `None` after an error if it's ambiguous.
+    fn elided(&mut self, span: Span) -> Option<Lifetime> {
+        let borrowing = match &self.lifetime {
+            Ok(lifetime) => return Some(Lifetime::new(&lifetime.to_string(), span)),
+            Err(_) if self.reported => return None,
+            Err(borrowing) => borrowing,
+        };
+        self.reported = true;
+        let diagnostic = span.unwrap().error("missing lifetime specifier");
+        match &borrowing[..] {
+            [] => diagnostic
+                .note("the iterator item has no arguments it could borrow from")
+                .help("if it yields values borrowed for the whole program, use `'static`"),
+            borrowing => diagnostic
+                .note(format!(
+                    "it could borrow from {}, and has no `&self` to default to",
+                    borrowing
+                        .iter()
+                        .map(|arg| format!("`{}`", arg))
+                        .collect::<Vec<_>>()
+                        .join(" or "),
+                ))
+                .help(
+                    "add a lifetime parameter like `'a` to the iterator item, and use it both \
+                     here and in the argument it borrows from",
+                ),
+        }
+        .emit();
+        None
+    }
+}
+
+impl Fold for UnelideOutput {
+    fn fold_lifetime(&mut self, lifetime: Lifetime) -> Lifetime {
+        if lifetime.ident == "_" {
+            self.elided(lifetime.span()).unwrap_or(lifetime)
+        } else {
+            lifetime
+        }
+    }
+
+    fn fold_type_reference(&mut self, ty_ref: TypeReference) -> TypeReference {
+        let lifetime = match ty_ref.lifetime {
+            Some(lifetime) => Some(self.fold_lifetime(lifetime)),
+            None => self.elided(ty_ref.and_token.span),
+        };
+        TypeReference {
+            lifetime,
+            elem: Box::new(self.fold_type(*ty_ref.elem)),
+            ..ty_ref
+        }
+    }
+
+    // Lifetimes elided in the arguments of `fn` pointers and `Fn` traits are their own.
+    fn fold_type_bare_fn(&mut self, ty: TypeBareFn) -> TypeBareFn {
+        ty
+    }
+
+    fn fold_parenthesized_generic_arguments(
+        &mut self,
+        args: ParenthesizedGenericArguments,
+    ) -> ParenthesizedGenericArguments {
+        args
+    }
+}
+
 struct UnelideLifetimes<'a> {
     generics: &'a mut Punctuated<GenericParam, Comma>,
     lifetime_index: usize,
@@ -0,0 +1,93 @@
+//! Support for `for await pat in stream { .. }` loops in `async` iterator items.
+//!
+//! `syn` can't parse `for await`, so before parsing we turn it into `#[__iterator_item_for_await]
+//! for`, which it can. `Visitor` then looks for that marker to desugar the loop into
+//! `iterator_item::async_gen_for_await!`.
+
+use proc_macro2::{Group, Ident, Spacing, TokenStream, TokenTree};
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::visit_mut::VisitMut;
+use syn::*;
+
+const MARKER: &str = "__iterator_item_for_await";
+
+/// Replace every `for await` in `tokens` with `#[__iterator_item_for_await] for`.
+pub fn mark(tokens: TokenStream) -> TokenStream {
+    let mut output: Vec<TokenTree> = vec![];
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(tt) = tokens.next() {
+        match tt {
+            TokenTree::Ident(ident) if ident == "for" => {
+                if matches!(tokens.peek(), Some(TokenTree::Ident(next)) if next == "await") {
+                    let await_token = tokens.next().unwrap();
+                    // Attributes go before the label: `#[marker] 'a: for`.
+                    let label_len = if is_label(&output) { 3 } else { 0 };
+                    let label = output.split_off(output.len() - label_len);
+                    let marker = Ident::new(MARKER, await_token.span());
+                    output.extend(quote!(#[#marker]));
+                    output.extend(label);
+                }
+                output.push(TokenTree::Ident(ident));
+            }
+            TokenTree::Group(group) => {
+                let mut new = Group::new(group.delimiter(), mark(group.stream()));
+                new.set_span(group.span());
+                output.push(TokenTree::Group(new));
+            }
+            tt => output.push(tt),
+        }
+    }
+    output.into_iter().collect()
+}
+
+/// Whether `tokens` ends in a loop label, `'a:`.
+fn is_label(tokens: &[TokenTree]) -> bool {
+    match tokens {
+        [.., TokenTree::Punct(quote), TokenTree::Ident(_), TokenTree::Punct(colon)] => {
+            quote.as_char() == '\''
+                && quote.spacing() == Spacing::Joint
+                && colon.as_char() == ':'
+                && colon.spacing() == Spacing::Alone
+        }
+        _ => false,
+    }
+}
+
+/// Whether the attributes of a `for` loop contain the marker, meaning it is a `for await` loop.
+pub fn has_marker(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| attr.path.is_ident(MARKER))
+}
+
+/// Remove the marker from the attributes of a `for` loop, returning whether it was there.
+pub fn take_marker(attrs: &mut Vec<Attribute>) -> bool {
+    let len = attrs.len();
+    attrs.retain(|attr| !attr.path.is_ident(MARKER));
+    attrs.len() != len
+}
+
+/// Emit an error for a `for await` loop that we can't desugar.
+pub fn unsupported(for_loop: &ExprForLoop, reason: &str) {
+    for_loop
+        .for_token
+        .span()
+        .join(for_loop.pat.span())
+        .unwrap_or_else(|| for_loop.for_token.span())
+        .unwrap()
+        .error(format!("`for await` loops are only supported {}", reason))
+        .emit();
+}
+
+/// `for await` loops inside of closures, `async` blocks and nested items in the body of an
+/// iterator item can't be desugared, because the `Context` of the iterator item isn't available
+/// there.
+pub struct Nested;
+
+impl VisitMut for Nested {
+    fn visit_expr_for_loop_mut(&mut self, i: &mut ExprForLoop) {
+        if take_marker(&mut i.attrs) {
+            unsupported(i, "directly in the body of `async` iterator items");
+        }
+        syn::visit_mut::visit_expr_for_loop_mut(self, i);
+    }
+}
@@ -0,0 +1,355 @@
+//! Anonymous iterator items, written as `gen! { .. }` expressions.
+//!
+//! The body is desugared in the same way as the body of a `fn*`, but the generator captures its
+//! environment like a `move` closure instead of taking arguments, and the expression evaluates to
+//! the iterator item directly.
+//!
+//! `gen!(|args| { .. })` is a closure that instead evaluates to a fresh iterator item when it is
+//! called, with the generator capturing the closure's arguments and moving its captures, which
+//! makes it `FnOnce`. With `#[clone_captures]`, every call gets a clone of the captures instead.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::fold::Fold;
+use syn::parse::{Parse, ParseStream, Result};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::*;
+
+use crate::syntax::Profile;
+use crate::{
+    desugaring_attributes, elision, guesses_try_yield, is_try_yield, size_hint, Desugaring,
+};
+
+/// AST of a `gen!` block.
+///
+/// Either just the statements of the body, `gen! { yield 1; }`, or a header followed by the body
+/// in braces, `gen! { #[size_hint((1, Some(1)))] static async yields i32 { yield 1; } }`. With
+/// closure arguments in the header, `gen!(async move |x: &str| yields u8 { .. })`, it's a closure.
+pub struct GenBlockParse {
+    attributes: Vec<Attribute>,
+    is_boxed: bool,
+    is_async: bool,
+    closure: Option<ClosureArgs>,
+    yields: Option<Type>,
+    body: Block,
+}
+
+/// `move |a, b: &T|`, the arguments of a `gen!` closure.
+struct ClosureArgs {
+    move_token: Option<Token![move]>,
+    inputs: Punctuated<Pat, Token![,]>,
+}
+
+/// `|a, b: &T|` or `||`. Copied from `syn`, where it is private too.
+fn parse_closure_inputs(input: ParseStream) -> Result<Punctuated<Pat, Token![,]>> {
+    let mut inputs = Punctuated::new();
+    if input.parse::<Option<Token![||]>>()?.is_some() {
+        return Ok(inputs);
+    }
+    input.parse::<Token![|]>()?;
+    loop {
+        if input.peek(Token![|]) {
+            break;
+        }
+        let attrs = input.call(Attribute::parse_outer)?;
+        let mut pat: Pat = input.parse()?;
+        if input.peek(Token![:]) {
+            pat = Pat::Type(PatType {
+                attrs,
+                pat: Box::new(pat),
+                colon_token: input.parse()?,
+                ty: input.parse()?,
+            });
+        } else if let Some(attr) = attrs.first() {
+            return Err(Error::new(
+                attr.span(),
+                "attributes are only supported on typed closure arguments",
+            ));
+        }
+        inputs.push_value(pat);
+        if input.peek(Token![|]) {
+            break;
+        }
+        inputs.push_punct(input.parse()?);
+    }
+    input.parse::<Token![|]>()?;
+    Ok(inputs)
+}
+
+/// `#[attr] static async move |args| yields Ty { .. }`, every part of the header being optional.
+fn parse_header(input: ParseStream) -> Result<GenBlockParse> {
+    let attributes = input.call(Attribute::parse_outer)?;
+    // `static async` or `async static`
+    let mut r#static: Option<Token![static]> = input.parse()?;
+    let r#async: Option<Token![async]> = input.parse()?;
+    if r#static.is_none() {
+        r#static = input.parse()?;
+    }
+    // The generator always captures by value, `move` is allowed on blocks for symmetry with
+    // closures. On closures it makes the closure itself capture by value.
+    let move_token: Option<Token![move]> = input.parse()?;
+    let closure = if input.peek(Token![|]) || input.peek(Token![||]) {
+        Some(ClosureArgs {
+            move_token,
+            inputs: parse_closure_inputs(input)?,
+        })
+    } else {
+        None
+    };
+
+    // `yields Ty`
+    // `yield Ty`
+    // `-> Ty`
+    // `=> Ty`
+    let marker_span = input.span();
+    let marker = if input.peek(Token![yield]) {
+        input.parse::<Token![yield]>()?;
+        Some("yield")
+    } else if input.peek(Token![->]) {
+        input.parse::<Token![->]>()?;
+        Some("->")
+    } else if input.peek(Token![=>]) {
+        input.parse::<Token![=>]>()?;
+        Some("=>")
+    } else if crate::peek_keyword(input, "yields") {
+        input.parse::<Ident>()?;
+        Some("yields")
+    } else {
+        None
+    };
+    let yields = match marker {
+        Some(marker) => {
+            // `gen!` blocks have no header to pick a profile, they use the crate's.
+            Profile::from_features().check_marker(marker, marker_span.unwrap());
+            Some(input.parse()?)
+        }
+        None => None,
+    };
+    Ok(GenBlockParse {
+        attributes,
+        is_boxed: r#static.is_some(),
+        is_async: r#async.is_some(),
+        closure,
+        yields,
+        body: input.parse()?,
+    })
+}
+
+impl Parse for GenBlockParse {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let fork = input.fork();
+        if parse_header(&fork).is_ok() && fork.is_empty() {
+            return parse_header(input);
+        }
+        Ok(GenBlockParse {
+            attributes: vec![],
+            is_boxed: false,
+            is_async: false,
+            closure: None,
+            yields: None,
+            body: Block {
+                brace_token: Default::default(),
+                stmts: input.call(Block::parse_within)?,
+            },
+        })
+    }
+}
+
+impl GenBlockParse {
+    pub fn build(self) -> TokenStream {
+        let GenBlockParse {
+            mut attributes,
+            is_boxed,
+            is_async,
+            closure,
+            yields,
+            body,
+        } = self;
+        let mut desugaring = Desugaring {
+            is_async,
+            is_boxed,
+            // Without a yield type `?` returns early, it can't yield the error.
+            is_try_yield: yields.as_ref().is_some_and(is_try_yield),
+            guessed_try: yields.as_ref().is_some_and(|ty| guesses_try_yield(ty, &[])),
+            size_hint: None,
+            infer_size_hint: true,
+            // Blocks capture their environment instead, they have no arguments.
+            args: vec![],
+            exact_size: None,
+            exact_size_hint: false,
+            name: match closure {
+                Some(_) => "`gen!` closure".to_string(),
+                None => "`gen!` block".to_string(),
+            },
+            yields: yields.clone(),
+            dyn_bounds: None,
+            has_returns: false,
+            receives: None,
+        };
+        desugaring.take_attributes(&mut attributes);
+        let mut clone_captures = false;
+        attributes.retain(|attr| {
+            let is_clone_captures = attr.path.is_ident("clone_captures") && attr.tokens.is_empty();
+            if is_clone_captures && closure.is_none() {
+                attr.span()
+                    .unwrap()
+                    .error("`#[clone_captures]` is only supported on `gen!` closures")
+                    .help("blocks build a single iterator item, they don't need to clone anything")
+                    .emit();
+            }
+            clone_captures |= is_clone_captures;
+            !is_clone_captures
+        });
+        for attr in attributes {
+            attr.span()
+                .unwrap()
+                .error(format!(
+                    "only {} are supported on `gen!` blocks, and `#[clone_captures]` on `gen!` \
+                     closures",
+                    desugaring_attributes()
+                ))
+                .emit();
+        }
+        let ClosureArgs { move_token, inputs } = match closure {
+            Some(closure) => closure,
+            None => {
+                let body = desugaring.expand(body);
+                return TokenStream::from(quote!({ #body }));
+            }
+        };
+
+        // Elided lifetimes in the arguments are named like they are in `fn*`s, and bound by a
+        // `for<..>` on the closure, which makes it higher-ranked over them.
+        let mut lifetimes = Punctuated::new();
+        let inputs = closure_inputs(&mut lifetimes, inputs);
+        let mut lifetimes: Vec<Lifetime> = lifetimes
+            .into_iter()
+            .filter_map(|param| match param {
+                GenericParam::Lifetime(def) => Some(def.lifetime),
+                _ => None,
+            })
+            .collect();
+        // A trait object can only have one lifetime bound, and a `for<..>` binder can't say that
+        // the others outlive it, so all the arguments share the first one.
+        let inputs = if lifetimes.len() > 1 {
+            let mut share = ShareLifetime(lifetimes.split_off(1), lifetimes[0].clone());
+            inputs.into_iter().map(|pat| share.fold_pat(pat)).collect()
+        } else {
+            inputs
+        };
+        if !lifetimes.is_empty() {
+            // The iterator item borrows from the arguments, so its type depends on their
+            // lifetimes. That can only be written as a trait object bounded by them.
+            desugaring.dyn_bounds.get_or_insert_with(Vec::new);
+            for input in &inputs {
+                if !matches!(input, Pat::Type(_)) {
+                    input
+                        .span()
+                        .unwrap()
+                        .error(
+                            "all the arguments of a `gen!` closure that borrows from its \
+                             arguments need a type",
+                        )
+                        .emit();
+                }
+            }
+        }
+        let binder = if lifetimes.is_empty() {
+            quote!()
+        } else {
+            quote!(for<#(#lifetimes),*>)
+        };
+        // Closures with a `for<..>` binder need their return type spelled out.
+        let return_type = match (&desugaring.dyn_bounds, &yields) {
+            (None, _) => quote!(),
+            (Some(bounds), Some(yields)) if is_async => quote! {
+                -> ::core::pin::Pin<::iterator_item::__internal::Box<
+                    dyn ::futures::stream::FusedStream<Item = #yields> #(+ #lifetimes)* #(+ #bounds)*
+                >>
+            },
+            (Some(bounds), Some(yields)) => quote! {
+                -> ::iterator_item::__internal::Box<
+                    dyn ::core::iter::FusedIterator<Item = #yields> #(+ #lifetimes)* #(+ #bounds)*
+                >
+            },
+            (Some(_), None) => {
+                inputs
+                    .span()
+                    .unwrap()
+                    .error("`gen!` closures that return a trait object need a yield type")
+                    .help("add `yields` and the type of the yielded values after the arguments")
+                    .emit();
+                quote!()
+            }
+        };
+        desugaring.args = size_hint::bindings(inputs.iter().map(|input| match input {
+            Pat::Type(input) => &*input.pat,
+            input => input,
+        }));
+        let body = desugaring.expand(body);
+        if !clone_captures {
+            return TokenStream::from(quote! {
+                #binder #move_token |#inputs| #return_type { #body }
+            });
+        }
+        // The generator moves the captures out of the closure that builds it, which makes that
+        // closure `FnOnce`. The closure that is returned builds each iterator item with a clone
+        // of it instead, so it can be called again, as long as the captures are `Clone`.
+        let forward: Vec<Ident> = (0..inputs.len())
+            .map(|i| Ident::new(&format!("__arg{}", i), proc_macro2::Span::call_site()))
+            .collect();
+        let forward_inputs = inputs.iter().zip(&forward).map(|(input, arg)| match input {
+            Pat::Type(PatType { ty, .. }) => quote!(#arg: #ty),
+            _ => quote!(#arg),
+        });
+        let clone = quote::quote_spanned!(inputs.span()=> ::core::clone::Clone::clone);
+        TokenStream::from(quote! {{
+            let __gen_closure = #binder #move_token |#inputs| #return_type { #body };
+            #binder move |#(#forward_inputs),*| #return_type {
+                #clone(&__gen_closure)(#(#forward),*)
+            }
+        }})
+    }
+}
+
+/// Replaces the lifetimes in `.0` with `.1`.
+struct ShareLifetime(Vec<Lifetime>, Lifetime);
+
+impl Fold for ShareLifetime {
+    fn fold_lifetime(&mut self, lifetime: Lifetime) -> Lifetime {
+        if self.0.contains(&lifetime) {
+            self.1.clone()
+        } else {
+            lifetime
+        }
+    }
+}
+
+/// Name the elided lifetimes in the types of `inputs`, adding them to `lifetimes`.
+fn closure_inputs(
+    lifetimes: &mut Punctuated<GenericParam, Token![,]>,
+    inputs: Punctuated<Pat, Token![,]>,
+) -> Punctuated<Pat, Token![,]> {
+    let args = inputs
+        .into_iter()
+        .map(|pat| match pat {
+            Pat::Type(pat) => FnArg::Typed(pat),
+            // Untyped arguments can't have elided lifetimes, keep them as they are.
+            pat => FnArg::Typed(PatType {
+                attrs: vec![],
+                pat: Box::new(pat),
+                colon_token: Default::default(),
+                ty: Box::new(Type::Infer(parse_quote!(_))),
+            }),
+        })
+        .collect();
+    elision::unelide_lifetimes(lifetimes, args)
+        .into_iter()
+        .map(|arg| match arg {
+            FnArg::Typed(PatType { pat, ty, .. }) if matches!(*ty, Type::Infer(_)) => *pat,
+            FnArg::Typed(pat) => Pat::Type(pat),
+            FnArg::Receiver(_) => unreachable!("closure arguments can't be `self`"),
+        })
+        .collect()
+}
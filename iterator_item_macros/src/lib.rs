@@ -2,12 +2,22 @@
 
 use proc_macro::TokenStream;
 use quote::quote;
+use syn::ext::IdentExt;
+use syn::parse::discouraged::Speculative;
 use syn::parse::{Parse, ParseStream, Result};
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::visit_mut::VisitMut;
 use syn::*;
 mod elision;
+mod for_await;
+mod gen_block;
+mod mac;
+mod size_hint;
+mod syntax;
+mod yield_from;
+
+use syntax::Profile;
 
 /// AST of an iterator item. Similar to an `Item::Fn`
 ///
@@ -18,81 +28,81 @@ struct IteratorItemParse {
     attributes: Vec<Attribute>,
     size_hint: SizeHint,
     visibility: Visibility,
+    /// `static fn*` or `#[boxed]`: the generator is `static` and pinned on the heap, so it can hold
+    /// borrows of its own locals across `yield`s.
+    is_boxed: bool,
     is_async: bool,
+    /// `default fn*`, for specialization.
+    defaultness: Option<Token![default]>,
+    /// Rejected in `build`, iterator items can't be `const`.
+    constness: Option<Token![const]>,
+    unsafety: Option<Token![unsafe]>,
+    /// Only `extern "Rust"` is accepted in `build`.
+    abi: Option<Abi>,
     name: Ident,
     generics: Generics,
     args: Punctuated<FnArg, Token![,]>,
     yields: Option<Type>,
+    /// `returns Ty`, the type of the value returned after the last `yield`.
+    returns: Option<Type>,
+    /// `receives Ty` or `receives pat: Ty`, the type of the values passed in on every resume.
+    receives: Option<Receives>,
     return_ty: Option<Type>,
-    body: Block,
+    /// `None` for declarations of trait methods without a default body.
+    body: Option<Block>,
 }
 
-fn parse_fn(input: ParseStream) -> Result<IteratorItemParse> {
+fn parse_fn(input: ParseStream, profile: &Profile) -> Result<IteratorItemParse> {
     // `#[attr(..)] #[attr2] pub async`
     let attributes: Vec<Attribute> = input.call(Attribute::parse_outer)?;
     let visibility: Visibility = input.parse()?;
-    let r#async: Option<Token![async]> = input.parse()?;
-
-    let mut regular_fn = true;
+    // `static`, `async`, `default`, `const`, `unsafe` and `extern "Rust"`, in any order.
+    let mut r#static: Option<Token![static]> = None;
+    let mut r#async: Option<Token![async]> = None;
+    let mut defaultness: Option<Token![default]> = None;
+    let mut constness: Option<Token![const]> = None;
+    let mut unsafety: Option<Token![unsafe]> = None;
+    let mut abi: Option<Abi> = None;
+    loop {
+        if r#static.is_none() && input.peek(Token![static]) {
+            r#static = input.parse()?;
+        } else if r#async.is_none() && input.peek(Token![async]) {
+            r#async = input.parse()?;
+        } else if defaultness.is_none()
+            && input.peek(Token![default])
+            // `default()` is an iterator item called `default` missing its keyword.
+            && !input.peek2(token::Paren)
+            && !input.peek2(Token![<])
+        {
+            defaultness = input.parse()?;
+        } else if constness.is_none() && input.peek(Token![const]) {
+            constness = input.parse()?;
+        } else if unsafety.is_none() && input.peek(Token![unsafe]) {
+            unsafety = input.parse()?;
+        } else if abi.is_none() && input.peek(Token![extern]) {
+            abi = input.parse()?;
+        } else {
+            break;
+        }
+    }
 
     // `fn foo(<args>)`
     // `fn* foo(<args>)`
     // `gen fn foo(<args>)`
     // `gen foo(<args>)`
+    // `r#gen fn foo(<args>)`
+    // `k#gen fn foo(<args>)`, in the 2018 edition
     // `generator fn foo(<args>)`
     // `generator foo(<args>)`
     // `iterator fn foo(<args>)`
     // `iterator foo(<args>)`
-    // `iter fn foo(<args>)`
-    // `ited foo(<args>)`
-    let lookahead = input.lookahead1();
-    if lookahead.peek(Token![fn]) {
-        input.parse::<Token![fn]>()?;
-        let lookahead = input.lookahead1();
-        if lookahead.peek(Token![*]) {
-            regular_fn = false;
-            input.parse::<Token![*]>()?;
-        }
-    } else {
-        // Parse expected `gen` keyword. That's not currently a token, so hack it up.
-        let gen: Option<Ident> = input.parse()?;
-        let tokens = &["gen", "generator", "iterator"];
-        if let Some(gen) = gen {
-            if !tokens.contains(&gen.to_string().as_str()) {
-                return Err(Error::new(
-                    gen.span().unwrap().into(),
-                    format!(
-                        "expected one of the following keywords to identify an iterator item: {}",
-                        tokens
-                            .iter()
-                            .map(|t| format!("`{}`", t))
-                            .collect::<Vec<_>>()
-                            .join(", "),
-                    ),
-                ));
-            }
-        } else {
-            return Err(Error::new(
-                input.span().unwrap().into(),
-                format!(
-                    "expected one of the following keywords to identify an iterator item: {}",
-                    tokens
-                        .iter()
-                        .map(|t| format!("`{}`", t))
-                        .collect::<Vec<_>>()
-                        .join(", "),
-                ),
-            ));
-        }
-        let lookahead = input.lookahead1();
-        if lookahead.peek(Token![fn]) {
-            input.parse::<Token![fn]>()?;
-        }
-        regular_fn = false;
-    }
+    let head_span = input.span();
+    let head = parse_head(input, profile)?;
+    profile.check_head(&head, head_span.unwrap());
+    let regular_fn = head == "fn";
 
     let name: Ident = input.parse()?;
-    let generics: Generics = input.parse()?;
+    let mut generics: Generics = input.parse()?;
     let fn_args;
     parenthesized!(fn_args in input);
     let args = parse_fn_args(&fn_args)?;
@@ -101,60 +111,291 @@ fn parse_fn(input: ParseStream) -> Result<IteratorItemParse> {
     // `=> Ty`
     // `yield Ty`
     // `yields Ty`
-    let lookahead = input.lookahead1();
-    let yields: Option<Type> = if lookahead.peek(Token![->]) {
-        if regular_fn {
-            name.span()
-                .unwrap()
-                .error("this looks like regular function, don't wrap it in `iterator_item` macro")
-                .help("use `iterator` attribute instead")
-                .emit();
-        }
+    let marker_span = input.span();
+    let marker = if input.peek(Token![->]) {
         input.parse::<Token![->]>()?;
-        Some(input.parse()?)
-    } else if lookahead.peek(Token![yield]) {
+        Some("->")
+    } else if input.peek(Token![yield]) {
         input.parse::<Token![yield]>()?;
-        Some(input.parse()?)
-    } else if lookahead.peek(Token![=>]) {
+        Some("yield")
+    } else if input.peek(Token![=>]) {
         input.parse::<Token![=>]>()?;
-        Some(input.parse()?)
+        Some("=>")
+    } else if peek_keyword(input, "yields") {
+        input.parse::<Ident>()?;
+        Some("yields")
+    } else if peek_keyword(input, "returns")
+        || peek_keyword(input, "receives")
+        || input.is_empty()
+        || input.peek(token::Brace)
+        || input.peek(Token![;])
+        || input.peek(Token![where])
+    {
+        None
     } else {
-        let yields: Option<Ident> = input.parse()?;
-        if let Some(yields) = yields {
-            if yields != "yields" {
-                return Err(Error::new(
-                    yields.span().unwrap().into(),
-                    "expected one of `yields`, `yield`, `->`, `=>` to indicate the value being \
-                     yielded by the iterator item",
-                ));
-                // FIXME: potentially deal better with this and try to recover the parse in a way
-                // that doesn't spam an user that forgot to write yields or tried to write `->`.
+        Some(recover_marker(input, profile)?)
+    };
+    let yields: Option<Type> = match marker {
+        Some(marker) => {
+            profile.check_marker(marker, marker_span.unwrap());
+            // Only if the profile accepts `->`, otherwise it already explained why it doesn't.
+            if regular_fn && marker == "->" && profile.accepts_marker("->") {
+                name.span()
+                    .unwrap()
+                    .error(
+                        "this looks like regular function, don't wrap it in `iterator_item` macro",
+                    )
+                    .help("use `iterator` attribute instead")
+                    .emit();
             }
             Some(input.parse()?)
-        } else {
-            None
         }
+        None => None,
     };
 
-    let body: Block = input.parse()?;
+    // `returns Ty`
+    // `receives Ty`
+    // `receives pat: Ty`
+    let mut returns: Option<Type> = None;
+    let mut receives: Option<Receives> = None;
+    loop {
+        if returns.is_none() && peek_keyword(input, "returns") {
+            input.parse::<Ident>()?;
+            returns = Some(input.parse()?);
+        } else if receives.is_none() && peek_keyword(input, "receives") {
+            input.parse::<Ident>()?;
+            receives = Some(input.parse()?);
+        } else {
+            break;
+        }
+    }
+
+    // `where T: Trait`
+    generics.where_clause = input.parse()?;
+
+    // `{ .. }`
+    // `;`
+    let body: Option<Block> = if input.peek(Token![;]) {
+        input.parse::<Token![;]>()?;
+        None
+    } else {
+        Some(input.parse()?)
+    };
     Ok(IteratorItemParse {
         attributes,
         size_hint: SizeHint { expr: None },
         visibility,
+        is_boxed: r#static.is_some(),
         is_async: r#async.is_some(),
+        defaultness,
+        constness,
+        unsafety,
+        abi,
         name,
         generics,
         args,
         yields,
+        returns,
+        receives,
         return_ty: None,
         body,
     })
 }
 
+/// There's something other than a marker before the body. If it's a typo of one, like `yeilds i32`,
+/// or the type without one, like `i32`, we carry on as if the marker was there, so that only one
+/// error is emitted and the body is still checked.
+///
+/// `proc_macro` can't attach suggestions that tools can apply, so the fix is a help message
+/// pointing at where it goes instead.
+fn recover_marker(input: ParseStream, profile: &Profile) -> Result<&'static str> {
+    let expected = format!(
+        "expected one of {} to indicate the value being yielded by the iterator item",
+        profile.accepted_markers(),
+    );
+    let fork = input.fork();
+    if let Ok(ident) = fork.call(Ident::parse_any) {
+        if let Some(marker) = profile.suggest_marker(&ident.to_string()) {
+            if fork.parse::<Type>().is_ok() {
+                input.call(Ident::parse_any)?;
+                ident
+                    .span()
+                    .unwrap()
+                    .error(format!("{}, found `{}`", expected, ident))
+                    .span_help(
+                        ident.span().unwrap(),
+                        format!("replace `{}` with `{}`", ident, marker),
+                    )
+                    .emit();
+                return Ok(marker);
+            }
+        }
+    }
+    let fork = input.fork();
+    match fork.parse::<Type>() {
+        Ok(ty) => {
+            let marker = profile.preferred_marker();
+            ty.span()
+                .unwrap()
+                .error(format!(
+                    "missing `{}` before the type of the yielded values",
+                    marker
+                ))
+                .span_help(
+                    ty.span().unwrap().start(),
+                    format!("add `{} ` here", marker),
+                )
+                .emit();
+            Ok(marker)
+        }
+        Err(_) => Err(Error::new(input.span().unwrap().into(), expected)),
+    }
+}
+
+/// The keyword that introduces an iterator item, spelled like the syntax profiles refer to it.
+fn parse_head(input: ParseStream, profile: &Profile) -> Result<String> {
+    if input.parse::<Option<Token![fn]>>()?.is_some() {
+        let star: Option<Token![*]> = input.parse()?;
+        return Ok(if star.is_some() { "fn*" } else { "fn" }.to_string());
+    }
+    // `k#gen` is three tokens in the 2018 edition. From the 2021 edition on it's a reserved prefix,
+    // which the lexer rejects before we see it.
+    let prefix = if peek_keyword(input, "k") && input.peek2(Token![#]) {
+        input.parse::<Ident>()?;
+        input.parse::<Token![#]>()?;
+        "k#"
+    } else {
+        ""
+    };
+    // Parse expected `gen` keyword. That's not currently a token, so hack it up.
+    let keywords = &["gen", "generator", "iterator"];
+    let expected = format!(
+        "expected one of the following keywords to identify an iterator item: {}",
+        profile.accepted_heads(),
+    );
+    // `foo()` or `foo<T>()`: the keyword is missing. We carry on as if the one the profile
+    // prefers was there, so that the rest of the item is still checked.
+    if prefix.is_empty()
+        && input.peek(Ident)
+        && (input.peek2(token::Paren) || input.peek2(Token![<]))
+    {
+        let head = profile.preferred_head();
+        let span = input.span().unwrap();
+        span.error("missing the keyword that identifies an iterator item")
+            .span_help(span.start(), format!("add `{} ` here", head))
+            .emit();
+        return Ok(head.to_string());
+    }
+    let gen: Option<Ident> = input.parse()?;
+    let mut head = match gen {
+        // `r#gen` is how `gen` is written from the 2024 edition on.
+        Some(gen) if keywords.contains(&gen.to_string().trim_start_matches("r#")) => {
+            format!("{}{}", prefix, gen)
+        }
+        // `genn fn`: carry on with the keyword it most likely is a typo of.
+        Some(gen) => match profile.suggest_head_keyword(&gen.to_string()) {
+            Some(keyword) => {
+                gen.span()
+                    .unwrap()
+                    .error(format!("{}, found `{}`", expected, gen))
+                    .span_help(
+                        gen.span().unwrap(),
+                        format!("replace `{}` with `{}`", gen, keyword),
+                    )
+                    .emit();
+                if keyword == "fn" {
+                    let star: Option<Token![*]> = input.parse()?;
+                    return Ok(if star.is_some() { "fn*" } else { "fn" }.to_string());
+                }
+                format!("{}{}", prefix, keyword)
+            }
+            // `foo fn`: we don't know what it is, but it's where the keyword goes.
+            None => {
+                gen.span().unwrap().error(expected).emit();
+                if input.parse::<Option<Token![fn]>>()?.is_some() {
+                    input.parse::<Option<Token![*]>>()?;
+                }
+                return Ok(profile.preferred_head().to_string());
+            }
+        },
+        None => return Err(Error::new(input.span().unwrap().into(), expected)),
+    };
+    if input.parse::<Option<Token![fn]>>()?.is_some() {
+        head.push_str(" fn");
+    }
+    Ok(head)
+}
+
+/// The values a coroutine item receives every time it is resumed: `receives Ty` or
+/// `receives pat: Ty`. The first of them is bound to `pat`, and the following ones are what each
+/// `yield` evaluates to.
+struct Receives {
+    pat: Pat,
+    ty: Type,
+}
+
+impl Parse for Receives {
+    fn parse(input: ParseStream) -> Result<Self> {
+        // `Vec<u8>` or `&[u8]` also parse as patterns, so we need to look for the `:`.
+        let fork = input.fork();
+        let is_pat = fork.parse::<Pat>().is_ok() && fork.peek(Token![:]) && !fork.peek(Token![::]);
+        let pat = if is_pat {
+            let pat = input.parse()?;
+            input.parse::<Token![:]>()?;
+            pat
+        } else {
+            parse_quote!(_)
+        };
+        Ok(Receives {
+            pat,
+            ty: input.parse()?,
+        })
+    }
+}
+
+/// `dyn` followed by extra bounds for the trait object, like `dyn + Send`.
+fn parse_dyn_bounds(input: ParseStream) -> Result<Vec<TypeParamBound>> {
+    input.parse::<Token![dyn]>()?;
+    let mut bounds = vec![];
+    while !input.is_empty() {
+        input.parse::<Token![+]>()?;
+        bounds.push(input.parse()?);
+    }
+    Ok(bounds)
+}
+
+/// Whether the next token is the contextual keyword `keyword`.
+fn peek_keyword(input: ParseStream, keyword: &str) -> bool {
+    matches!(input.fork().parse::<Ident>(), Ok(ident) if ident == keyword)
+}
+
 impl Parse for IteratorItemParse {
     /// Hi! If you are looking to hack on this crate to come up with your own syntax, **look here**!
+    /// Or at `syntax.rs`, if you only need to allow or forbid some of the existing spellings.
     fn parse(input: ParseStream) -> Result<Self> {
-        parse_fn(input)
+        // `syntax = "gen-arrow";`
+        let profile = if peek_keyword(input, "syntax") && input.peek2(Token![=]) {
+            input.parse::<Ident>()?;
+            input.parse::<Token![=]>()?;
+            let name: LitStr = input.parse()?;
+            input.parse::<Token![;]>()?;
+            Profile::by_name(&name.value()).ok_or_else(|| {
+                let names: Vec<_> = syntax::PROFILES
+                    .iter()
+                    .map(|profile| format!("`{}`", profile.name))
+                    .collect();
+                Error::new(
+                    name.span(),
+                    format!(
+                        "unknown syntax profile, expected one of {}",
+                        names.join(", ")
+                    ),
+                )
+            })?
+        } else {
+            Profile::from_features()
+        };
+        parse_fn(input, profile)
     }
 }
 
@@ -164,14 +405,48 @@ impl IteratorItemParse {
             mut attributes,
             size_hint,
             visibility,
+            is_boxed,
             is_async,
+            defaultness,
+            constness,
+            unsafety,
+            abi,
             name,
             mut generics,
             args,
             yields,
+            returns,
+            receives,
             return_ty,
-            mut body,
+            body,
         } = self;
+        if let Some(constness) = constness {
+            constness
+                .span
+                .unwrap()
+                .error("iterator items can't be `const`")
+                .note(
+                    "calling one only builds the iterator, its body runs when it's iterated, \
+                     which can't happen at compile time",
+                )
+                .emit();
+        }
+        let abi = match abi {
+            Some(abi) if matches!(&abi.name, Some(name) if name.value() == "Rust") => Some(abi),
+            Some(abi) => {
+                abi.span()
+                    .unwrap()
+                    .error("iterator items can only use the `Rust` ABI")
+                    .note(
+                        "they return an opaque `impl Iterator` type, which can't cross an FFI \
+                         boundary",
+                    )
+                    .help("remove the `extern`, or use `extern \"Rust\"`")
+                    .emit();
+                None
+            }
+            None => None,
+        };
         let yields = match yields {
             Some(ty) => ty,
             None => Type::Tuple(TypeTuple {
@@ -180,107 +455,590 @@ impl IteratorItemParse {
             }),
         };
         let args = elision::unelide_lifetimes(&mut generics.params, args);
+        // The return type of `#[iterator]` functions is left to `rustc`, which elides its
+        // lifetimes itself.
+        let (yields, returns) = match return_ty {
+            Some(_) => (yields, returns),
+            None => (
+                elision::unelide_output_lifetimes(&args, yields),
+                returns.map(|ty| elision::unelide_output_lifetimes(&args, ty)),
+            ),
+        };
         let lifetimes: Vec<syn::Lifetime> =
             generics.lifetimes().map(|l| l.lifetime.clone()).collect();
+        let receives = match receives {
+            Some(receives) if is_async => {
+                receives
+                    .ty
+                    .span()
+                    .unwrap()
+                    .error("`async` iterator items can't receive values when resumed")
+                    .emit();
+                None
+            }
+            // Elided lifetimes in the received type get their own lifetime parameters. They
+            // aren't in `lifetimes`, the iterator item doesn't need to outlive them.
+            Some(Receives { pat, ty }) => Some(Receives {
+                pat,
+                ty: elision::unelide_type_lifetimes(&mut generics.params, ty),
+            }),
+            None => None,
+        };
 
-        let is_try_yield = match yields {
-            // This would be much nicer in `rustc` desugaring because we'd have access to name resolution.
-            Type::Path(TypePath {
-                qself: None,
-                ref path,
-            }) => {
-                let is_try = path
-                    .segments
-                    .first()
-                    .map_or(false, |s| s.ident == "Result" || s.ident == "Option");
-                path.segments.len() == 1 && is_try
+        let mut desugaring = Desugaring {
+            is_async,
+            is_boxed,
+            is_try_yield: is_try_yield(&yields),
+            guessed_try: guesses_try_yield(
+                &yields,
+                &generics
+                    .type_params()
+                    .map(|param| param.ident.clone())
+                    .collect::<Vec<_>>(),
+            ),
+            size_hint: size_hint.expr.map(|expr| quote!(#expr)),
+            infer_size_hint: true,
+            args: size_hint::bindings(args.iter().filter_map(|arg| match arg {
+                FnArg::Typed(arg) => Some(&*arg.pat),
+                FnArg::Receiver(_) => None,
+            })),
+            exact_size: None,
+            exact_size_hint: false,
+            name: format!("`{}`", name),
+            // The return type names it.
+            yields: None,
+            dyn_bounds: None,
+            has_returns: returns.is_some(),
+            receives,
+        };
+        desugaring.take_attributes(&mut attributes);
+        let receives = &desugaring.receives;
+
+        let exact_size = match desugaring.exact_size {
+            Some(_) => quote!(+ ::core::iter::ExactSizeIterator),
+            None => quote!(),
+        };
+        // The generator captures every argument, so the `impl Trait` has to capture all of their
+        // lifetimes. `+ 'a + 'b` would also require it to outlive each of them, which it can't
+        // when one of them is shorter than the other, as in `&'a [&'b str]`. `Captures` only
+        // names them.
+        let captures: Vec<TypeParamBound> = lifetimes
+            .iter()
+            .map(|lifetime| parse_quote!(::iterator_item::__internal::Captures<#lifetime>))
+            .collect();
+        let captures_bounds = quote!(#(+ #captures)*);
+        let return_type = if let Some(mut ty) = return_ty {
+            // Like the `impl Trait` we write for `fn*`s, the one from `#[iterator]` has to capture
+            // all the lifetimes. Its bounds are left as written, and only the lifetimes they
+            // don't mention yet are added.
+            let captured = elision::captured_lifetimes(&args, ty.clone());
+            if let Type::ImplTrait(TypeImplTrait { bounds, .. }) = &mut ty {
+                bounds.extend(
+                    lifetimes
+                        .iter()
+                        .zip(captures)
+                        .filter(|(lifetime, _)| !captured.contains(lifetime))
+                        .map(|(_, capture)| capture),
+                );
+            }
+            quote!(#ty)
+        } else if let Some(bounds) = &desugaring.dyn_bounds {
+            // A trait object can only have one lifetime bound.
+            let lifetime = match &lifetimes[..] {
+                [] => quote!(),
+                [lifetime] => quote!(+ #lifetime),
+                lifetimes => {
+                    let lifetime = elision::outlived_lifetime(&mut generics, lifetimes);
+                    quote!(+ #lifetime)
+                }
+            };
+            let bounds = quote!(#lifetime #(+ #bounds)*);
+            if let Some(Receives { ty, .. }) = receives {
+                let returns = match &returns {
+                    Some(returns) => quote!(#returns),
+                    None => quote!(()),
+                };
+                quote! {
+                    ::iterator_item::__internal::Box<
+                        dyn ::iterator_item::Coroutine<#ty, Yield = #yields, Return = #returns>
+                            #bounds
+                    >
+                }
+            } else if is_async {
+                if let Some(returns) = &returns {
+                    returns
+                        .span()
+                        .unwrap()
+                        .error("`async` iterator items with `#[boxed(dyn)]` can't return a value")
+                        .emit();
+                }
+                quote! {
+                    ::core::pin::Pin<::iterator_item::__internal::Box<
+                        dyn ::futures::stream::FusedStream<Item = #yields> #bounds
+                    >>
+                }
+            } else if let Some(returns) = &returns {
+                quote! {
+                    ::iterator_item::__internal::Box<
+                        dyn ::iterator_item::IteratorReturn<Item = #yields, Return = #returns>
+                            #bounds
+                    >
+                }
+            } else {
+                quote! {
+                    ::iterator_item::__internal::Box<
+                        dyn ::core::iter::FusedIterator<Item = #yields> #bounds
+                    >
+                }
+            }
+        } else if let Some(Receives { ty, .. }) = receives {
+            let returns = match &returns {
+                Some(returns) => quote!(#returns),
+                None => quote!(()),
+            };
+            quote! {
+                impl ::iterator_item::Coroutine<#ty, Yield = #yields, Return = #returns>
+                    #captures_bounds
             }
-            _ => false,
+        } else if let Some(returns) = &returns {
+            if is_async {
+                quote! {
+                    impl ::futures::stream::FusedStream<Item = #yields>
+                        + ::iterator_item::StreamReturn<Return = #returns>
+                        #captures_bounds
+                }
+            } else {
+                quote! {
+                    impl ::core::iter::FusedIterator<Item = #yields>
+                        + ::iterator_item::IteratorReturn<Return = #returns>
+                        #exact_size
+                        #captures_bounds
+                }
+            }
+        } else if is_async {
+            // Whey don't we use `std`'s `Stream` here?
+            // `Stream` is currently on the process of being reworked into `AsyncIterator`[1],
+            // leveraging associated `async fn` support that isn't yet in nightly. For now, we
+            // just rely on the library that people are actually using, the futures' crate Stream.
+            // [1]: https://rust-lang.github.io/wg-async-foundations/vision/roadmap/async_iter/traits.html
+            // quote! { impl ::core::stream::Stream<Item = #yields> #(+ #lifetimes)* }
+            // `FusedStream: Stream`, so this is an `impl Stream<Item = #yields>`.
+            quote!(impl ::futures::stream::FusedStream<Item = #yields> #captures_bounds)
+        } else {
+            // `FusedIterator: Iterator`, so this is an `impl Iterator<Item = #yields>`.
+            quote!(impl ::core::iter::FusedIterator<Item = #yields> #exact_size #captures_bounds)
         };
-        let mut visitor = Visitor::new(is_async, is_try_yield);
-        visitor.visit_block_mut(&mut body);
-        let mut final_size_hint = quote!((0, None));
-        if let Some(size_hint) = size_hint.expr {
-            final_size_hint = quote!(#size_hint);
+        let args: Vec<_> = args.into_iter().collect();
+        let where_clause = &generics.where_clause;
+        let signature = quote! {
+            #(#attributes)* #visibility #defaultness #unsafety #abi
+                fn #name #generics(#(#args),*) -> #return_type #where_clause
+        };
+        let expanded = match body {
+            Some(body) => {
+                let body = desugaring.expand(body);
+                quote!(#signature { #body })
+            }
+            // A trait method without a default body.
+            None => quote!(#signature;),
+        };
+
+        TokenStream::from(expanded)
+    }
+}
+
+/// Whether `?` on a value of type `yields` should yield the error instead of returning.
+fn is_try_yield(yields: &Type) -> bool {
+    match yields {
+        // This would be much nicer in `rustc` desugaring because we'd have access to name resolution.
+        // `Result`, `io::Result` and `std::option::Option` all count.
+        Type::Path(TypePath { qself: None, path }) => matches!(
+            path.segments.last(),
+            Some(s) if s.ident == "Result" || s.ident == "Option"
+        ),
+        _ => false,
+    }
+}
+
+/// Whether `is_try_yield` had to guess for `yields`: it isn't a `Result` or an `Option`, but its
+/// name says it likely is an alias of one, like `ParseResult<T>`. The type parameters in
+/// `params`, and their associated types, aren't aliases.
+fn guesses_try_yield(yields: &Type, params: &[Ident]) -> bool {
+    match yields {
+        _ if is_try_yield(yields) => false,
+        Type::Path(TypePath { qself: None, path }) => {
+            let is_param = matches!(path.segments.first(), Some(s) if params.contains(&s.ident));
+            let name = match path.segments.last() {
+                Some(segment) => segment.ident.to_string(),
+                None => return false,
+            };
+            !is_param && (name.ends_with("Result") || name.ends_with("Option"))
         }
+        _ => false,
+    }
+}
+
+/// Everything needed to turn the body of an iterator item into the expression that builds it,
+/// shared by `fn*` items and `gen!` blocks.
+struct Desugaring {
+    is_async: bool,
+    is_boxed: bool,
+    is_try_yield: bool,
+    /// Whether `is_try_yield` is a guess, which a `?` in the body warns about. `#[try_yield]` and
+    /// `#[try_stop]` pick one explicitly.
+    guessed_try: bool,
+    /// An explicit `size_hint`, instead of the inferred one or the default `(0, None)`.
+    size_hint: Option<proc_macro2::TokenStream>,
+    /// Whether to infer the `size_hint` from the body when there's no explicit one.
+    infer_size_hint: bool,
+    /// The bindings of the arguments, whose `size_hint` the inferred one can depend on.
+    args: Vec<Ident>,
+    /// The span of `#[exact_size]` or `#[size_hint(exact = ..)]`, which make the iterator item an
+    /// `ExactSizeIterator`.
+    exact_size: Option<proc_macro2::Span>,
+    /// Whether `size_hint` is `#[size_hint(exact = ..)]`, which counts the items exactly.
+    exact_size_hint: bool,
+    /// How runtime panic messages refer to the iterator item, like `` `foo` ``.
+    name: String,
+    /// The type it yields, for `gen!` blocks whose expansion doesn't name it anywhere else.
+    yields: Option<Type>,
+    /// `#[boxed(dyn)]` and the extra bounds of the trait object.
+    dyn_bounds: Option<Vec<TypeParamBound>>,
+    has_returns: bool,
+    receives: Option<Receives>,
+}
+
+/// The attributes that `Desugaring::take_attributes` removes, for the errors about the others.
+const DESUGARING_ATTRIBUTES: &[&str] = &[
+    "size_hint",
+    "exact_size",
+    "no_size_hint",
+    "boxed",
+    "boxed(dyn)",
+    "try_yield",
+    "try_stop",
+];
+
+/// `DESUGARING_ATTRIBUTES` as a list for error messages: `` `#[a]`, `#[b]` and `#[c]` ``.
+fn desugaring_attributes() -> String {
+    let attributes: Vec<String> = DESUGARING_ATTRIBUTES
+        .iter()
+        .map(|attr| format!("`#[{}]`", attr))
+        .collect();
+    let (last, rest) = attributes.split_last().unwrap();
+    format!("{} and {}", rest.join(", "), last)
+}
+
+impl Desugaring {
+    /// Remove the attributes that configure the desugaring, like `#[size_hint]`. Keep
+    /// `DESUGARING_ATTRIBUTES` in sync with them.
+    fn take_attributes(&mut self, attributes: &mut Vec<Attribute>) {
+        let mut try_attr: Option<proc_macro2::Span> = None;
         attributes.retain(|attr| {
+            // `#[try_yield]` makes `?` yield the error and then stop, and `#[try_stop]` makes it
+            // stop without yielding anything, whatever the yield type looks like.
+            let try_yield = attr.path.is_ident("try_yield");
+            if (try_yield || attr.path.is_ident("try_stop")) && attr.tokens.is_empty() {
+                if let Some(previous) = try_attr {
+                    attr.span()
+                        .unwrap()
+                        .error("`?` can only be configured once")
+                        .span_note(previous.unwrap(), "it was already configured here")
+                        .emit();
+                } else if self.has_returns {
+                    attr.span()
+                        .unwrap()
+                        .error("`?` returns the error in iterator items with a `returns` type")
+                        .emit();
+                }
+                try_attr = Some(attr.span());
+                self.is_try_yield = try_yield;
+                self.guessed_try = false;
+                return false;
+            }
             // An annotation of the type `#[size_hint((0, None))] fn* foo() { ... }` lets the end
             // user provide code to override the default return of `Iterator::size_hint`.
+            // Without it, we infer one from simple bodies, see `size_hint::infer`.
             // FIXME: verify if an alternative name should be considered.
             // Once we do this is in the compiler, we can observe the materialized types of all the
-            // arguments, *and* thier uses, so that for simpler cases where iterators are being
-            // consumed once and without nesting, we can come up with an accurate `size_hint` (or
-            // at least as accurate as the `size_hint()` call is for the inputs).
-            // FIXME: we can do some of the above by modifying `Visitor` to keep track of renames
-            // and reassigns of the input bindings and of them being iterated on in for loops, but
-            // this will be tricky to get right.
+            // arguments, *and* thier uses, so that we can come up with an accurate `size_hint` in
+            // more cases than the syntactic ones we handle.
             if attr.path.get_ident().map(|a| a.to_string()).as_deref() == Some("size_hint") {
-                final_size_hint = attr.tokens.clone();
+                match attr.parse_args_with(parse_exact_size_hint) {
+                    // `#[size_hint(exact = len)]` is `#[size_hint((len, Some(len)))]` and
+                    // `#[exact_size]` together.
+                    Ok(len) => {
+                        self.size_hint = Some(quote! {{
+                            let len: usize = #len;
+                            (len, ::core::option::Option::Some(len))
+                        }});
+                        self.exact_size = Some(attr.span());
+                        self.exact_size_hint = true;
+                    }
+                    Err(_) => {
+                        self.size_hint = Some(attr.tokens.clone());
+                        self.exact_size_hint = false;
+                    }
+                }
                 // We are removing the attribute from the desugaring because we are parsing it
                 // directly.
                 false
+            } else if attr.path.is_ident("exact_size") && attr.tokens.is_empty() {
+                self.exact_size = Some(attr.span());
+                false
+            } else if attr.path.is_ident("no_size_hint") && attr.tokens.is_empty() {
+                // `#[no_size_hint]` keeps the default `(0, None)` instead of inferring one, for
+                // when the arguments' `size_hint`s can't be trusted or are expensive to compute.
+                self.infer_size_hint = false;
+                false
+            } else if attr.path.is_ident("boxed") && attr.tokens.is_empty() {
+                // `#[boxed]` is the same as `static fn*`, but it also works with `#[iterator]`.
+                self.is_boxed = true;
+                false
+            } else if attr.path.is_ident("boxed") {
+                // `#[boxed(dyn)]` or `#[boxed(dyn + Send)]` returns a trait object, so that the
+                // iterator item can be a method of a dyn compatible trait. It is `#[boxed]` too.
+                match attr.parse_args_with(parse_dyn_bounds) {
+                    Ok(bounds) => {
+                        self.dyn_bounds = Some(bounds);
+                        self.is_boxed = true;
+                    }
+                    Err(err) => err.span().unwrap().error(err.to_string()).emit(),
+                }
+                false
             } else {
                 true
             }
         });
+    }
+
+    /// Desugar `body` into the statements that build the iterator item.
+    fn expand(&self, mut body: Block) -> proc_macro2::TokenStream {
+        let Desugaring {
+            is_async,
+            is_boxed,
+            is_try_yield,
+            guessed_try,
+            ref size_hint,
+            infer_size_hint,
+            ref args,
+            exact_size,
+            exact_size_hint,
+            ref name,
+            ref yields,
+            ref dyn_bounds,
+            has_returns,
+            ref receives,
+        } = *self;
+        // Inferred from the body as written, before it is desugared.
+        let inferred_size_hint = match (receives, size_hint) {
+            (None, None) if infer_size_hint => size_hint::infer(&body, args),
+            _ => None,
+        };
+        // Without an explicit `size_hint`, `yield from` updates it with the delegate's.
+        yield_from::mark_tail(&mut body);
+        // Coroutine items aren't iterators, they have no `size_hint` to update.
+        let mut visitor = Visitor::new(
+            is_async,
+            is_try_yield,
+            guessed_try,
+            size_hint.is_none() && receives.is_none(),
+            has_returns,
+            receives.is_some(),
+        );
+        visitor.visit_block_mut(&mut body);
 
         // The `yield panic!()` in the desugaring is to allow an empty body in the input to still
         // expand to a generator. `rustc` relies on the presence of a `yield` statement in a
-        // closure body to turn it into a generator.
-        let tail = quote! {
+        // closure body to turn it into a generator. The body comes last so that its value is what
+        // the generator returns.
+        // In `gen!` blocks, nothing else names the type they yield, so this one also constrains it.
+        let head_value = match (yields, is_async) {
+            (Some(yields), false) => {
+                quote!(::iterator_item::__internal::GenYield::<#yields>::Item(panic!()))
+            }
+            (Some(yields), true) => quote! {
+                ::core::task::Poll::Ready(
+                    ::iterator_item::__internal::GenYield::<#yields>::Item(panic!())
+                )
+            },
+            (None, _) => quote!(panic!()),
+        };
+        let head_yield = quote! {
             #[allow(unreachable_code)]
-            {
-                return;
-                yield panic!();
+            if false {
+                yield #head_value;
             }
         };
-        let return_type = if let Some(ty) = return_ty {
-            quote!(#ty)
+        // Only a `size_hint` that was declared is checked, an inferred one can't be wrong.
+        let checked = size_hint.is_some();
+        let expansion = if receives.is_some() {
+            quote!(::iterator_item::__internal::CoroutineItem {
+                gen,
+                complete: false,
+            })
         } else if is_async {
-            // Whey don't we use `std`'s `Stream` here?
-            // `Stream` is currently on the process of being reworked into `AsyncIterator`[1],
-            // leveraging associated `async fn` support that isn't yet in nightly. For now, we
-            // just rely on the library that people are actually using, the futures' crate Stream.
-            // [1]: https://rust-lang.github.io/wg-async-foundations/vision/roadmap/async_iter/traits.html
-            // quote! { impl ::core::stream::Stream<Item = #yields> #(+ #lifetimes)* }
-            quote!(impl ::futures::stream::Stream<Item = #yields> #(+ #lifetimes)*)
+            quote!(::iterator_item::__internal::AsyncIteratorItem {
+                gen,
+                name: #name,
+                size_hint,
+                checked: #checked,
+                yielded: 0,
+                complete: false,
+                return_value: None,
+            })
         } else {
-            quote!(impl ::core::iter::Iterator<Item = #yields> #(+ #lifetimes)*)
+            quote!(::iterator_item::__internal::IteratorItem {
+                gen,
+                name: #name,
+                size_hint,
+                checked: #checked,
+                yielded: 0,
+                complete: false,
+                return_value: None,
+            })
         };
-        let expansion = if is_async {
-            quote!(::iterator_item::__internal::AsyncIteratorItem { gen, size_hint })
-        } else {
-            quote!(::iterator_item::__internal::IteratorItem { gen, size_hint })
+        let expansion = match exact_size {
+            Some(span) => {
+                let exact = match &inferred_size_hint {
+                    Some((_, exact)) => *exact,
+                    None => exact_size_hint,
+                };
+                self.exact_size_expansion(span, exact, visitor.set_size_hint, expansion)
+            }
+            None => expansion,
+        };
+        let expansion = match (dyn_bounds, is_async) {
+            (Some(_), true) => quote!(::iterator_item::__internal::Box::pin(#expansion)),
+            (Some(_), false) => quote!(::iterator_item::__internal::Box::new(#expansion)),
+            (None, _) => expansion,
         };
         let head = if is_async {
-            quote!(static move |mut __stream_ctx|)
+            quote!(static move |mut __stream_ctx: ::iterator_item::__internal::StreamCtx|)
+        } else if let Some(Receives { pat, ty }) = receives {
+            if is_boxed {
+                quote!(static move |#pat: #ty|)
+            } else {
+                quote!(move |#pat: #ty|)
+            }
+        } else if is_boxed {
+            quote!(static move ||)
         } else {
             quote!(move ||)
         };
-        let args: Vec<_> = args.into_iter().collect();
-        // Consider modifying this so that `gen` is `let gen = Box::pin(gen);`
-        let expanded = quote! {
-            #(#attributes)* #visibility fn #name #generics(#(#args),*) -> #return_type {
+        // A `static` generator isn't `Unpin`, but a pinned `Box` of it is, and it is a `Generator`
+        // too. `async` iterator items are always `static`, boxing them makes them `Unpin`.
+        let pin_gen = if is_boxed {
+            quote!(let gen = ::iterator_item::__internal::Box::pin(gen);)
+        } else {
+            quote!()
+        };
+        let size_hint = match (receives, size_hint) {
+            (Some(_), _) => quote!(),
+            (None, Some(size_hint)) => quote! {
                 #[allow(unused_parens)]
-                let size_hint = #final_size_hint;
-                let gen = #head {
-                    #body
-                    #tail
-                };
-                #expansion
+                let size_hint = #size_hint;
+            },
+            (None, None) => match inferred_size_hint {
+                Some((size_hint, _)) => quote!(let size_hint = #size_hint;),
+                None => quote!(let size_hint = (0, None);),
+            },
+        };
+        // Without a `returns` type the body must evaluate to `()`, like it has to in a function
+        // that doesn't declare what it returns.
+        let body = if has_returns {
+            quote!(#body)
+        } else {
+            quote!(let () = #body;)
+        };
+        quote! {
+            #size_hint
+            let gen = #head {
+                #head_yield
+                #body
+            };
+            #pin_gen
+            #expansion
+        }
+    }
+
+    /// Wrap `expansion` to make it an `ExactSizeIterator`, because of the attribute at `span`. Its
+    /// `size_hint` has to be `exact` from the start, and `set_size_hint!` can't change it after.
+    fn exact_size_expansion(
+        &self,
+        span: proc_macro2::Span,
+        exact: bool,
+        set_size_hint: Option<proc_macro2::Span>,
+        expansion: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let error = if self.is_async {
+            span.unwrap()
+                .error("`async` iterator items can't be `ExactSizeIterator`s")
+                .note("`Stream` has no equivalent of `ExactSizeIterator`")
+        } else if self.receives.is_some() {
+            span.unwrap()
+                .error("iterator items with a `receives` type aren't iterators")
+                .note("`#[exact_size]` makes an iterator item an `ExactSizeIterator`")
+        } else if self.dyn_bounds.is_some() {
+            span.unwrap()
+                .error("`#[exact_size]` can't be used on iterator items that return a trait object")
+                .note("a trait object can only have one trait that isn't an auto trait")
+        } else {
+            if !exact {
+                // Without one, `ExactSizeIterator::len` would panic. The return type is still an
+                // `ExactSizeIterator`, so we keep wrapping the expansion to avoid a type error.
+                span.unwrap()
+                    .error("`#[exact_size]` needs a `size_hint` that is exact and known up front")
+                    .note(
+                        "only `#[size_hint(exact = ..)]`, or a body that always yields the same \
+                         number of items, guarantee one",
+                    )
+                    .help("use `#[size_hint(exact = len)]`, with the number of items it yields")
+                    .emit();
             }
+            if let Some(set_size_hint) = set_size_hint {
+                set_size_hint
+                    .unwrap()
+                    .error(
+                        "`set_size_hint!` can't change the `size_hint` of an `ExactSizeIterator`",
+                    )
+                    .span_note(
+                        span.unwrap(),
+                        "it is an `ExactSizeIterator` because of this",
+                    )
+                    .emit();
+            }
+            return quote!(::iterator_item::__internal::ExactSizeIteratorItem(#expansion));
         };
+        error.emit();
+        expansion
+    }
+}
 
-        TokenStream::from(expanded)
+/// `exact = len`, in `#[size_hint(exact = len)]`.
+fn parse_exact_size_hint(input: ParseStream) -> Result<Expr> {
+    let exact: Ident = input.parse()?;
+    if exact != "exact" {
+        return Err(Error::new(exact.span(), "expected `exact`"));
     }
+    input.parse::<Token![=]>()?;
+    input.parse()
 }
 
 #[proc_macro]
 pub fn iterator_item(input: TokenStream) -> TokenStream {
+    let input = TokenStream::from(yield_from::mark(for_await::mark(input.into())));
     let item: IteratorItemParse = parse_macro_input!(input as IteratorItemParse);
     item.build()
 }
 
+#[proc_macro]
+pub fn gen(input: TokenStream) -> TokenStream {
+    let input = TokenStream::from(yield_from::mark(for_await::mark(input.into())));
+    let block = parse_macro_input!(input as gen_block::GenBlockParse);
+    block.build()
+}
+
 struct SizeHint {
     expr: Option<Expr>,
 }
@@ -306,83 +1064,254 @@ impl Parse for SizeHint {
 
 #[proc_macro_attribute]
 pub fn iterator(attr: TokenStream, input: TokenStream) -> TokenStream {
-    let item = parse_macro_input!(input as Item);
-    let item = match item {
-        Item::Fn(item) => item,
-        _ => panic!("other"),
-    };
-    //let size_hint: Result<Expr> = syn::parse(attr);
-    //let size_hint = size_hint.unwrap();
+    let input = TokenStream::from(yield_from::mark(for_await::mark(input.into())));
+    let mut item = parse_macro_input!(input as IteratorFn);
     let size_hint = parse_macro_input!(attr as SizeHint);
-    //if let Some(expr) = size_hint.expr {
-    //    println!("{:#?}", quote!(#expr));
-    //}
-    //println!("{:#?}", size_hint);
-
-    let return_ty = match item.sig.output {
-        ReturnType::Default => panic!("expected `impl Iterator<Output = Ty>` return type"),
-        ReturnType::Type(_, ty) => {
-            match *ty {
-                Type::ImplTrait(TypeImplTrait { .. }) => {
-                    // FIXME: we want to evaluate the returned `impl Trait` to verify it is a valid
-                    // iterator that can be used with `yield`. This would also help with better
-                    // typechk diagnostics and appropriate handling of anon lifetimes.
-                    //
-                    // for bound in bounds {
-                    //     match bound {
-                    //         TypeParamBound::Trait(bound) => {
-                    //             for segment in bound.path.segments {
-                    //                 if let PathArguments::AngleBracketed(args) = segment.arguments {
-                    //                     for arg in args.args {
-                    //                         if let GenericArgument::Constraint(constraint) = arg {
-                    //                             if constraint.ident == "Item" {
-                    //                                 for bound in contraint.bounds {
-                    //                                     break (ty, bound);
-                    //                                 }
-                    //                             }
-                    //                         }
-                    //                     }
-                    //                 }
-                    //             }
-                    //         }
-                    //         _ => continue,
-                    //     }
-                    // }
-                    // panic!("`Item` not found");
-                    ty
-                }
-                _ => panic!("expected `impl Iterator<Output = Ty>` return type"),
+    // The wrapper around the generator can only run it from the front.
+    if let ReturnType::Type(_, ty) = &mut item.sig.output {
+        if let Type::ImplTrait(TypeImplTrait { bounds, .. }) = &mut **ty {
+            let double_ended = |bound: &TypeParamBound| is_trait(bound, "DoubleEndedIterator");
+            for bound in bounds.iter().filter(|bound| double_ended(bound)) {
+                bound
+                    .span()
+                    .unwrap()
+                    .error("iterator items can't be `DoubleEndedIterator`s")
+                    .note("they only run from the front")
+                    .emit();
             }
+            *bounds = std::mem::take(bounds)
+                .into_iter()
+                .filter(|bound| !double_ended(bound))
+                .collect();
         }
+    }
+    let (return_ty, yields) = match iterator_return_type(&item.sig) {
+        Ok(types) => types,
+        Err(err) => return err.to_compile_error().into(),
     };
+    let sig = item.sig;
+    let mut attributes = item.attrs;
+    if mentions_trait(&return_ty, "ExactSizeIterator") {
+        attributes.push(parse_quote_spanned!(return_ty.span()=> #[exact_size]));
+    }
+    // The return type is the one written on the function, it can't be a boxed trait object.
+    for attr in &mut attributes {
+        if attr.path.is_ident("boxed") && !attr.tokens.is_empty() {
+            attr.span()
+                .unwrap()
+                .error("`#[boxed(dyn)]` can't change the return type of `#[iterator]` functions")
+                .help("use `#[boxed]`, or a `fn*` in an `iterator_item!`")
+                .emit();
+            *attr = parse_quote_spanned!(attr.span()=> #[boxed]);
+        }
+    }
     IteratorItemParse {
-        attributes: item.attrs,
+        attributes,
         size_hint,
         visibility: item.vis,
-        is_async: item.sig.asyncness.is_some(),
-        name: item.sig.ident,
-        generics: item.sig.generics,
-        args: item.sig.inputs,
-        yields: None,
-        return_ty: Some(*return_ty),
-        body: *item.block,
+        is_boxed: false,
+        is_async: sig.asyncness.is_some(),
+        defaultness: item.defaultness,
+        constness: sig.constness,
+        unsafety: sig.unsafety,
+        abi: sig.abi,
+        name: sig.ident,
+        generics: sig.generics,
+        args: sig.inputs,
+        yields: Some(yields),
+        returns: None,
+        receives: None,
+        return_ty: Some(return_ty),
+        body: item.block,
     }
     .build()
 }
 
+/// Whether `ty` is an `impl Trait` with `name` among its bounds.
+fn mentions_trait(ty: &Type, name: &str) -> bool {
+    match ty {
+        Type::ImplTrait(TypeImplTrait { bounds, .. }) => {
+            bounds.iter().any(|bound| is_trait(bound, name))
+        }
+        _ => false,
+    }
+}
+
+/// Whether `bound` is the trait `name`.
+fn is_trait(bound: &TypeParamBound, name: &str) -> bool {
+    match bound {
+        TypeParamBound::Trait(TraitBound { path, .. }) => {
+            matches!(path.segments.last(), Some(segment) if segment.ident == name)
+        }
+        TypeParamBound::Lifetime(_) => false,
+    }
+}
+
+/// A function or method annotated with `#[iterator]`.
+struct IteratorFn {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    defaultness: Option<Token![default]>,
+    sig: Signature,
+    /// `None` for declarations of trait methods without a default body.
+    block: Option<Block>,
+}
+
+impl Parse for IteratorFn {
+    fn parse(input: ParseStream) -> Result<Self> {
+        // Methods in traits, with or without a default body, and free functions without a
+        // visibility. `ImplItemMethod` would accept the former with a placeholder body.
+        let fork = input.fork();
+        if let Ok(method) = fork.parse::<TraitItemMethod>() {
+            input.advance_to(&fork);
+            return Ok(IteratorFn {
+                attrs: method.attrs,
+                vis: Visibility::Inherited,
+                defaultness: None,
+                sig: method.sig,
+                block: method.default,
+            });
+        }
+        // Free functions, and methods in `impl` blocks.
+        let fork = input.fork();
+        if let Ok(method) = fork.parse::<ImplItemMethod>() {
+            input.advance_to(&fork);
+            return Ok(IteratorFn {
+                attrs: method.attrs,
+                vis: method.vis,
+                defaultness: method.defaultness,
+                sig: method.sig,
+                block: Some(method.block),
+            });
+        }
+        // Anything else is either not a function, or a function with a syntax error, in which
+        // case parsing it as an item points at the error.
+        let item: Item = input.parse()?;
+        Err(Error::new(
+            item.span(),
+            "the `iterator` attribute can only be applied to functions and methods",
+        ))
+    }
+}
+
+/// The return type of a `#[iterator]` function, which has to be an `impl Iterator<Item = Ty>`, or
+/// an `impl Stream<Item = Ty>` if it's `async`, and the `Ty` it yields.
+fn iterator_return_type(sig: &Signature) -> Result<(Type, Type)> {
+    let is_async = sig.asyncness.is_some();
+    let expected = if is_async {
+        "`impl Stream<Item = Ty>`"
+    } else {
+        "`impl Iterator<Item = Ty>`"
+    };
+    let ty = match &sig.output {
+        ReturnType::Default => {
+            return Err(Error::new(
+                sig.paren_token.span,
+                format!("expected {} return type after the arguments", expected),
+            ))
+        }
+        ReturnType::Type(_, ty) => ty,
+    };
+    let bounds = match &**ty {
+        Type::ImplTrait(TypeImplTrait { bounds, .. }) => bounds,
+        ty => {
+            return Err(Error::new(
+                ty.span(),
+                format!("expected {} return type", expected),
+            ))
+        }
+    };
+    // This would be much nicer in `rustc` desugaring because we'd have access to name resolution,
+    // here we can only go by the name of the trait.
+    let (sync_traits, async_traits): (&[&str], &[&str]) = (
+        &["Iterator", "FusedIterator", "ExactSizeIterator"],
+        &["Stream", "FusedStream"],
+    );
+    for bound in bounds {
+        let segment = match bound {
+            TypeParamBound::Trait(TraitBound { path, .. }) => match path.segments.last() {
+                Some(segment) => segment,
+                None => continue,
+            },
+            TypeParamBound::Lifetime(_) => continue,
+        };
+        let name = segment.ident.to_string();
+        if !sync_traits.contains(&name.as_str()) && !async_traits.contains(&name.as_str()) {
+            continue;
+        }
+        if is_async && sync_traits.contains(&name.as_str()) {
+            return Err(Error::new(
+                segment.ident.span(),
+                format!(
+                    "`async` iterator items return a `Stream`, expected {} return type",
+                    expected,
+                ),
+            ));
+        }
+        if !is_async && async_traits.contains(&name.as_str()) {
+            return Err(Error::new(
+                segment.ident.span(),
+                format!("iterator items that return a `{}` need to be `async`", name),
+            ));
+        }
+        let item = match &segment.arguments {
+            PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+                GenericArgument::Binding(binding) if binding.ident == "Item" => {
+                    Some(binding.ty.clone())
+                }
+                _ => None,
+            }),
+            _ => None,
+        };
+        return match item {
+            Some(item) => Ok(((**ty).clone(), item)),
+            None => Err(Error::new(
+                segment.span(),
+                format!("expected `{}<Item = Ty>`, with the type it yields", name),
+            )),
+        };
+    }
+    Err(Error::new(
+        ty.span(),
+        format!("expected {} return type", expected),
+    ))
+}
+
 /// This `Visitor` allows us to modify the body (block) of the parsed item to make changes to it
 /// before passing it back to `rustc`. This allows us to construct our own desugaring for `await`
 /// and `yield`.
 struct Visitor {
     is_async: bool,
     is_try_yield: bool,
+    /// Whether `is_try_yield` is a guess we haven't warned about yet.
+    guessed_try: bool,
+    /// Whether `yield from` should update the iterator item's `size_hint` with its delegate's.
+    yield_from_size_hint: bool,
+    /// Whether the iterator item has a `returns` type, so it can `return` a value.
+    has_returns: bool,
+    /// Whether the iterator item has a `receives` type, so it has no `size_hint` to set.
+    is_coroutine: bool,
+    /// The first `set_size_hint!` in the body, if any.
+    set_size_hint: Option<proc_macro2::Span>,
 }
 
 impl Visitor {
-    fn new(is_async: bool, is_try_yield: bool) -> Self {
+    fn new(
+        is_async: bool,
+        is_try_yield: bool,
+        guessed_try: bool,
+        yield_from_size_hint: bool,
+        has_returns: bool,
+        is_coroutine: bool,
+    ) -> Self {
         Visitor {
             is_async,
             is_try_yield,
+            guessed_try,
+            yield_from_size_hint,
+            has_returns,
+            is_coroutine,
+            set_size_hint: None,
         }
     }
 }
@@ -393,16 +1322,20 @@ impl VisitMut for Visitor {
     /// This takes care of turning `async` iterators into a sync `Generator` body that is
     /// equivalent to the `rustc` desugared `async` code for `async`/`await`.
     fn visit_expr_mut(&mut self, i: &mut syn::Expr) {
+        match i {
+            // Closures and `async` blocks introduce their own scope: any `return`, `?`, `.await`
+            // or `yield` inside of them belongs to them, not to the iterator item.
+            syn::Expr::Closure(_) | syn::Expr::Async(_) => {
+                for_await::Nested.visit_expr_mut(i);
+                yield_from::Nested.visit_expr_mut(i);
+                return;
+            }
+            _ => {}
+        }
         // We traverse all the child nodes first.
         syn::visit_mut::visit_expr_mut(self, i);
         match i {
-            // FIXME: consider implementing `for await i in foo {}` syntax here by handling
-            // `syn::Expr::ForLoop`.
-            // FIXME: attempt to calculate `size_hint` proactively in loops by calling `size_hint`
-            // in the expression being iterated *before* building the generator. This can only work
-            // in very specific circumstances, so we need to be very clear that we are in one of
-            // the valid cases. If we do this, we need to also increment a counter for every
-            // `yield` statement outside of loops.
+            syn::Expr::Return(_) if self.has_returns => {}
             syn::Expr::Return(syn::ExprReturn { expr, .. }) => {
                 // To avoid further type errors down the line, explicitly handle this case and
                 // remove it from the resulting item body.
@@ -410,27 +1343,110 @@ impl VisitMut for Visitor {
                     expr.span()
                         .unwrap()
                         .error("iterator items can't return a non-`()` value")
-                        .help("returning in an iterator is only meant for stopping the iterator")
+                        .help(
+                            "to return a value after the last `yield`, add `returns` and its type \
+                             to the iterator item's signature",
+                        )
                         .emit();
                 }
                 *expr = None;
             }
+            syn::Expr::Yield(yield_expr) if yield_from::is_marked(yield_expr) => {
+                // Turn `yield from #expr` into a loop yielding every item of `#expr`
+                let (expr, tail) = yield_from::take(yield_expr);
+                let size_hint = match (self.yield_from_size_hint, tail) {
+                    (false, _) => quote!(),
+                    (true, false) => quote!(, size_hint),
+                    (true, true) => quote!(, size_hint, tail),
+                };
+                *i = if self.is_async {
+                    parse_quote!(iterator_item::async_gen_yield_from!(
+                        #expr, __stream_ctx #size_hint
+                    ))
+                } else {
+                    parse_quote!(iterator_item::gen_yield_from!(#expr #size_hint))
+                };
+            }
             syn::Expr::Yield(syn::ExprYield {
                 expr: Some(expr), ..
             }) if self.is_async => {
                 // Turn `yield #expr` in an `async` iterator item into `yield Poll::Ready(#expr)`
-                *i = parse_quote!(iterator_item::async_gen_yield!(#expr));
+                *i = parse_quote!(iterator_item::async_gen_yield!(#expr, __stream_ctx));
             }
             syn::Expr::Yield(syn::ExprYield { expr: None, .. }) if self.is_async => {
                 // Turn `yield;` in an `async` iterator item into `yield Poll::Ready(())`
-                *i = parse_quote!(iterator_item::async_gen_yield!(()));
+                *i = parse_quote!(iterator_item::async_gen_yield!((), __stream_ctx));
+            }
+            syn::Expr::Yield(syn::ExprYield {
+                expr: Some(expr), ..
+            }) => {
+                // Turn `yield #expr` into `yield GenYield::Item(#expr)`
+                *i = parse_quote!(iterator_item::gen_yield!(#expr));
+            }
+            syn::Expr::Yield(syn::ExprYield { expr: None, .. }) => {
+                *i = parse_quote!(iterator_item::gen_yield!(()));
             }
             syn::Expr::Await(syn::ExprAwait { base: expr, .. }) if self.is_async => {
                 // Turn `#expr.await` in an `async` iterator item into a `poll(#expr, cxt)` call
                 // (with more details, look at the macro for more)
                 *i = parse_quote!(iterator_item::async_gen_await!(#expr, __stream_ctx));
             }
-            syn::Expr::Try(syn::ExprTry { expr, .. }) => {
+            syn::Expr::ForLoop(for_loop) if for_await::has_marker(&for_loop.attrs) => {
+                for_await::take_marker(&mut for_loop.attrs);
+                if self.is_async {
+                    // Turn `for await #pat in #expr #body` into a loop that polls the pinned
+                    // `Stream` with the iterator item's `Context`.
+                    let syn::ExprForLoop {
+                        attrs,
+                        label,
+                        pat,
+                        expr,
+                        body,
+                        ..
+                    } = for_loop;
+                    // Braces, so that it doesn't need a `;` in statement position like `for`.
+                    let mut mac: syn::ExprMacro = parse_quote! {
+                        iterator_item::async_gen_for_await! {
+                            #label #pat in #expr, __stream_ctx => #body
+                        }
+                    };
+                    mac.attrs = std::mem::take(attrs);
+                    *i = syn::Expr::Macro(mac);
+                } else {
+                    for_await::unsupported(for_loop, "in `async` iterator items");
+                }
+            }
+            syn::Expr::Macro(_) => {
+                // Desugar the arguments of macros like `assert!` or `vec!`, and turn the
+                // `.await`s hidden in the expansion of `join!` and `select!` into ours.
+                self.visit_macro_expr(i);
+            }
+            syn::Expr::Try(syn::ExprTry { expr, .. }) if self.has_returns => {
+                // Turn `#expr?` into an early return, like in regular functions
+                *i = parse_quote!(iterator_item::gen_try_return!(#expr));
+            }
+            syn::Expr::Try(syn::ExprTry {
+                expr,
+                question_token,
+                ..
+            }) => {
+                if self.guessed_try {
+                    // Once per iterator item is enough.
+                    self.guessed_try = false;
+                    question_token
+                        .span()
+                        .unwrap()
+                        .warning("this `?` stops the iterator item without yielding the error")
+                        .note(
+                            "the yield type isn't a `Result` or an `Option`, but its name looks \
+                             like an alias of one",
+                        )
+                        .help(
+                            "add `#[try_yield]` to the iterator item to yield the error before \
+                             stopping, or `#[try_stop]` to only stop",
+                        )
+                        .emit();
+                }
                 *i = match (self.is_async, self.is_try_yield) {
                     // Turn `#expr?` into one last `yield #expr`
                     (true, true) => parse_quote!(iterator_item::async_gen_try!(#expr)),
@@ -445,6 +1461,37 @@ impl VisitMut for Visitor {
             _ => {}
         }
     }
+
+    /// Nested items (`fn`s, `impl` blocks, etc.) in the body are left untouched, they can't
+    /// `yield` from the iterator item.
+    fn visit_item_mut(&mut self, i: &mut syn::Item) {
+        for_await::Nested.visit_item_mut(i);
+        yield_from::Nested.visit_item_mut(i);
+    }
+
+    fn visit_stmt_mut(&mut self, i: &mut syn::Stmt) {
+        // `syn` parses macro invocations in statement position as items, but `assert!(x?);` is
+        // an expression we need to desugar. Turning it into an expression statement doesn't
+        // change the tokens we emit.
+        if let syn::Stmt::Item(syn::Item::Macro(syn::ItemMacro { ident: None, .. })) = i {
+            let stmt =
+                std::mem::replace(i, syn::Stmt::Expr(syn::Expr::Verbatim(Default::default())));
+            if let syn::Stmt::Item(syn::Item::Macro(syn::ItemMacro {
+                attrs,
+                mac,
+                semi_token,
+                ..
+            })) = stmt
+            {
+                let expr = syn::Expr::Macro(syn::ExprMacro { attrs, mac });
+                *i = match semi_token {
+                    Some(semi) => syn::Stmt::Semi(expr, semi),
+                    None => syn::Stmt::Expr(expr),
+                };
+            }
+        }
+        syn::visit_mut::visit_stmt_mut(self, i);
+    }
 }
 
 /// Copied from `syn` because it exists but it is private 🤷
@@ -463,23 +1510,24 @@ fn parse_fn_args(input: ParseStream) -> Result<Punctuated<FnArg, Token![,]>> {
             continue;
         } else {
             let mut arg: FnArg = input.parse()?;
-            match &mut arg {
-                FnArg::Receiver(receiver) if has_receiver => {
-                    return Err(Error::new(
-                        receiver.self_token.span,
-                        "unexpected second method receiver",
-                    ));
-                }
-                FnArg::Receiver(receiver) if !args.is_empty() => {
-                    return Err(Error::new(
-                        receiver.self_token.span,
-                        "unexpected method receiver",
-                    ));
+            // `syn` parses receivers with a type, like `self: Box<Self>`, as regular arguments.
+            let receiver_span = match &arg {
+                FnArg::Receiver(receiver) => Some(receiver.self_token.span),
+                FnArg::Typed(arg) if elision::is_receiver(arg) => Some(arg.pat.span()),
+                FnArg::Typed(_) => None,
+            };
+            match receiver_span {
+                Some(span) if has_receiver => {
+                    return Err(Error::new(span, "unexpected second method receiver"));
                 }
-                FnArg::Receiver(receiver) => {
-                    has_receiver = true;
-                    receiver.attrs = attrs;
+                Some(span) if !args.is_empty() => {
+                    return Err(Error::new(span, "unexpected method receiver"));
                 }
+                Some(_) => has_receiver = true,
+                None => {}
+            }
+            match &mut arg {
+                FnArg::Receiver(receiver) => receiver.attrs = attrs,
                 FnArg::Typed(arg) => arg.attrs = attrs,
             }
             arg
@@ -0,0 +1,418 @@
+//! Desugaring of `yield`, `?` and `.await` inside of macro invocations.
+//!
+//! The arguments of a macro call are an opaque `TokenStream` to `syn`, so `Visitor` never sees
+//! them. For the macros whose syntax we know, we parse their arguments, desugar them and put them
+//! back. For everything else, we point out anything we would have needed to rewrite.
+
+use proc_macro2::{Span, TokenStream, TokenTree};
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream, Result};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit_mut::VisitMut;
+use syn::*;
+
+use crate::Visitor;
+
+/// Macros that take a comma separated list of expressions. For the formatting macros the format
+/// string is just another expression, and named arguments (`name = value`) parse as assignments.
+const EXPR_MACROS: &[&str] = &[
+    "assert",
+    "assert_eq",
+    "assert_ne",
+    "dbg",
+    "debug_assert",
+    "debug_assert_eq",
+    "debug_assert_ne",
+    "eprint",
+    "eprintln",
+    "format",
+    "format_args",
+    "panic",
+    "print",
+    "println",
+    "todo",
+    "unimplemented",
+    "unreachable",
+    "vec",
+    "write",
+    "writeln",
+];
+
+/// Arguments of the macros we know how to desugar that can be rewritten in place.
+enum MacroArgs {
+    /// `vec![elem; n]`
+    Repeat(Box<Expr>, Token![;], Box<Expr>),
+    /// `format!("{} {x}", a, x = b)`, `assert!(cond, "{}", msg)`
+    List(Punctuated<Expr, Token![,]>),
+    /// `matches!(expr, pattern)`. We only touch the scrutinee, the pattern is kept as is.
+    Matches(Box<Expr>, TokenStream),
+}
+
+impl Parse for MacroArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.is_empty() {
+            return Ok(MacroArgs::List(Punctuated::new()));
+        }
+        let first: Expr = input.parse()?;
+        if input.peek(Token![;]) {
+            let semi = input.parse()?;
+            let count = input.parse()?;
+            return Ok(MacroArgs::Repeat(Box::new(first), semi, count));
+        }
+        let mut list = Punctuated::new();
+        list.push_value(first);
+        while !input.is_empty() {
+            list.push_punct(input.parse()?);
+            if input.is_empty() {
+                break;
+            }
+            list.push_value(input.parse()?);
+        }
+        Ok(MacroArgs::List(list))
+    }
+}
+
+fn parse_matches(input: ParseStream) -> Result<MacroArgs> {
+    let expr = input.parse()?;
+    let rest: TokenStream = input.parse()?;
+    Ok(MacroArgs::Matches(expr, rest))
+}
+
+/// `futures::select!` and `tokio::select!`
+struct Select {
+    /// `biased;`, only in `tokio::select!`
+    biased: Option<(Ident, Token![;])>,
+    branches: Vec<SelectBranch>,
+}
+
+enum SelectBranch {
+    /// `pat = fut => body` or `pat = fut, if cond => body`
+    Future {
+        pat: Pat,
+        fut: Box<Expr>,
+        cond: Option<Box<Expr>>,
+        body: Box<Expr>,
+    },
+    /// `complete => body`, `default => body` or `else => body`
+    Fallback {
+        keyword: TokenStream,
+        body: Box<Expr>,
+    },
+}
+
+impl Parse for Select {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let biased = if input.peek(Ident) && input.peek2(Token![;]) {
+            let biased: Ident = input.parse()?;
+            if biased != "biased" {
+                return Err(Error::new(biased.span(), "expected `biased`"));
+            }
+            Some((biased, input.parse()?))
+        } else {
+            None
+        };
+        let mut branches = vec![];
+        while !input.is_empty() {
+            let branch = if input.peek(Token![else]) && input.peek2(Token![=>]) {
+                let keyword: Token![else] = input.parse()?;
+                input.parse::<Token![=>]>()?;
+                SelectBranch::Fallback {
+                    keyword: quote!(#keyword),
+                    body: input.parse()?,
+                }
+            } else if input.peek(Ident) && input.peek2(Token![=>]) {
+                let keyword: Ident = input.parse()?;
+                input.parse::<Token![=>]>()?;
+                SelectBranch::Fallback {
+                    keyword: quote!(#keyword),
+                    body: input.parse()?,
+                }
+            } else {
+                let pat = input.parse()?;
+                input.parse::<Token![=]>()?;
+                let fut = input.parse()?;
+                let cond = if input.peek(Token![,]) && input.peek2(Token![if]) {
+                    input.parse::<Token![,]>()?;
+                    input.parse::<Token![if]>()?;
+                    Some(input.parse()?)
+                } else {
+                    None
+                };
+                input.parse::<Token![=>]>()?;
+                SelectBranch::Future {
+                    pat,
+                    fut,
+                    cond,
+                    body: input.parse()?,
+                }
+            };
+            let body = match &branch {
+                SelectBranch::Future { body, .. } | SelectBranch::Fallback { body, .. } => body,
+            };
+            // Like in `match` arms, the comma is optional after a block.
+            if !input.is_empty() && !matches!(**body, Expr::Block(_)) {
+                input.parse::<Token![,]>()?;
+            } else {
+                input.parse::<Option<Token![,]>>()?;
+            }
+            branches.push(branch);
+        }
+        Ok(Select { biased, branches })
+    }
+}
+
+impl Visitor {
+    /// Desugar the arguments of a macro invocation in the iterator item's body, replacing `expr`
+    /// entirely if the macro expands to an `.await`.
+    pub(crate) fn visit_macro_expr(&mut self, expr: &mut Expr) {
+        let mac = match expr {
+            Expr::Macro(ExprMacro { mac, .. }) => mac,
+            _ => return,
+        };
+        let name = match known_name(&mac.path) {
+            Some(name) => name,
+            None => return check_tokens(mac.tokens.clone(), &mac.path, self.is_async),
+        };
+        let name = name.as_str();
+        if name == "gen" {
+            // A nested `gen!` block is its own iterator item, like closures are left alone.
+            return;
+        }
+        if name == "set_size_hint" {
+            // Yield the new `size_hint` to the iterator item's wrapper, like `yield from` does.
+            let span = mac.path.span();
+            self.set_size_hint.get_or_insert(span);
+            let mut size_hint = match mac.parse_body::<Expr>() {
+                Ok(size_hint) => size_hint,
+                Err(err) => {
+                    err.span().unwrap().error(err.to_string()).emit();
+                    return;
+                }
+            };
+            self.visit_expr_mut(&mut size_hint);
+            *expr = if self.is_coroutine {
+                span.unwrap()
+                    .error("iterator items with a `receives` type don't have a `size_hint`")
+                    .emit();
+                parse_quote!(())
+            } else if self.is_async {
+                parse_quote!(iterator_item::async_gen_set_size_hint!(#size_hint, __stream_ctx))
+            } else {
+                parse_quote!(iterator_item::gen_set_size_hint!(#size_hint))
+            };
+            return;
+        }
+        if EXPR_MACROS.contains(&name) {
+            if let Ok(args) = mac.parse_body::<MacroArgs>() {
+                mac.tokens = self.desugar_args(args);
+                return;
+            }
+        } else if name == "matches" {
+            if let Ok(args) = mac.parse_body_with(parse_matches) {
+                mac.tokens = self.desugar_args(args);
+                return;
+            }
+        } else if self.is_async && (name == "join" || name == "try_join") {
+            let parser = Punctuated::<Expr, Token![,]>::parse_terminated;
+            if let Ok(futures) = mac.parse_body_with(parser) {
+                *expr = self.desugar_join(&mac.path, futures);
+                return;
+            }
+        } else if self.is_async && (name == "select" || name == "select_biased") {
+            if let Ok(select) = mac.parse_body::<Select>() {
+                *expr = self.desugar_select(&mac.path, select);
+                return;
+            }
+        }
+        check_tokens(mac.tokens.clone(), &mac.path, self.is_async);
+    }
+
+    fn desugar_args(&mut self, args: MacroArgs) -> TokenStream {
+        match args {
+            MacroArgs::Repeat(mut elem, semi, mut count) => {
+                self.visit_expr_mut(&mut elem);
+                self.visit_expr_mut(&mut count);
+                quote!(#elem #semi #count)
+            }
+            MacroArgs::List(mut list) => {
+                for expr in list.iter_mut() {
+                    self.visit_expr_mut(expr);
+                }
+                quote!(#list)
+            }
+            MacroArgs::Matches(mut expr, pat) => {
+                self.visit_expr_mut(&mut expr);
+                check_tokens(pat.clone(), &parse_quote!(matches), self.is_async);
+                quote!(#expr #pat)
+            }
+        }
+    }
+
+    /// `join!(a, b)` expands to an `.await`, which we can't rewrite, so we instead evaluate the
+    /// futures in the iterator item and then `.await` the `join!` in an `async` block:
+    ///
+    /// ```ignore
+    /// {
+    ///     let __join0 = a;
+    ///     let __join1 = b;
+    ///     iterator_item::async_gen_await!(async move { join!(__join0, __join1) }, __stream_ctx)
+    /// }
+    /// ```
+    fn desugar_join(&mut self, path: &Path, mut futures: Punctuated<Expr, Token![,]>) -> Expr {
+        let mut bindings = vec![];
+        for (i, fut) in futures.iter_mut().enumerate() {
+            self.visit_expr_mut(fut);
+            bindings.push(format_ident!("__join{}", i));
+        }
+        let futures = futures.iter();
+        parse_quote! {{
+            #(let #bindings = #futures;)*
+            iterator_item::async_gen_await!(
+                async move { #path!(#(#bindings),*) },
+                __stream_ctx
+            )
+        }}
+    }
+
+    /// `select!` expands to an `.await` too, but its branches can `yield`, `break` or use `?`, so
+    /// they need to stay in the iterator item. We `.await` the `select!` in an `async` block that
+    /// only tells us which branch was taken, and run the branch's body afterwards:
+    ///
+    /// ```ignore
+    /// {
+    ///     enum __IteratorItemSelect<__T0, __T1> { __Branch0(__T0), __Branch1(__T1), __Fallback0 }
+    ///     match iterator_item::async_gen_await!(async {
+    ///         select! {
+    ///             __value = a => __IteratorItemSelect::__Branch0(__value),
+    ///             __value = b => __IteratorItemSelect::__Branch1(__value),
+    ///             complete => __IteratorItemSelect::__Fallback0,
+    ///         }
+    ///     }, __stream_ctx) {
+    ///         __IteratorItemSelect::__Branch0(x) => { /* first body */ }
+    ///         __IteratorItemSelect::__Branch1(y) => { /* second body */ }
+    ///         __IteratorItemSelect::__Fallback0 => { /* `complete` body */ }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// This means that refutable patterns (only accepted by `tokio::select!`) are not supported.
+    fn desugar_select(&mut self, path: &Path, select: Select) -> Expr {
+        let mut params = vec![];
+        let mut variants = vec![];
+        let mut inner_branches = vec![];
+        let mut arms = vec![];
+        for branch in select.branches {
+            match branch {
+                SelectBranch::Future {
+                    pat,
+                    fut,
+                    cond,
+                    mut body,
+                } => {
+                    let param = format_ident!("__T{}", params.len());
+                    let variant = format_ident!("__Branch{}", params.len());
+                    let cond = cond.map(|cond| quote!(, if #cond));
+                    self.visit_expr_mut(&mut body);
+                    inner_branches.push(quote! {
+                        __value = #fut #cond => __IteratorItemSelect::#variant(__value)
+                    });
+                    arms.push(quote!(__IteratorItemSelect::#variant(#pat) => { #body }));
+                    variants.push(quote!(#variant(#param)));
+                    params.push(param);
+                }
+                SelectBranch::Fallback { keyword, mut body } => {
+                    let variant = format_ident!("__Fallback{}", variants.len() - params.len());
+                    self.visit_expr_mut(&mut body);
+                    inner_branches.push(quote!(#keyword => __IteratorItemSelect::#variant));
+                    arms.push(quote!(__IteratorItemSelect::#variant => { #body }));
+                    variants.push(quote!(#variant));
+                }
+            }
+        }
+        let biased = select.biased.map(|(biased, semi)| quote!(#biased #semi));
+        parse_quote! {{
+            #[allow(non_camel_case_types)]
+            enum __IteratorItemSelect<#(#params),*> {
+                #(#variants,)*
+            }
+            match iterator_item::async_gen_await!(
+                async { #path! { #biased #(#inner_branches,)* } },
+                __stream_ctx
+            ) {
+                #(#arms)*
+            }
+        }}
+    }
+}
+
+/// The name of the macro `path` refers to, if it's one whose arguments we know how to desugar:
+/// a bare name, or a path to it in a crate that provides it, like `std::vec` or `tokio::select`.
+/// A `foo::vec!` of some other crate can take anything.
+fn known_name(path: &Path) -> Option<String> {
+    let name = path.segments.last()?.ident.to_string();
+    let krate = match path.segments.len() {
+        1 if path.leading_colon.is_none() => return Some(name),
+        2 => path.segments[0].ident.to_string(),
+        _ => return None,
+    };
+    let crates: &[&str] = match name.as_str() {
+        "gen" | "set_size_hint" => &["iterator_item"],
+        "join" | "try_join" | "select" => &["futures", "tokio"],
+        "select_biased" => &["futures"],
+        _ => &["std", "core", "alloc"],
+    };
+    crates.contains(&krate.as_str()).then_some(name)
+}
+
+/// We don't know how to parse the arguments of the macro at `path`, so we can't desugar them.
+/// Point out the first `yield` in `tokens`, or `.await` if `is_async`: if the macro passes it
+/// through to its expansion, it will fail to compile in confusing ways. It might not, like the
+/// `yield`s of a nested generator macro, so this is only a note, not an error or a warning.
+fn check_tokens(tokens: TokenStream, path: &Path, is_async: bool) {
+    if let Some((span, kind)) = find_desugared_token(tokens, is_async) {
+        let name = path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect::<Vec<_>>()
+            .join("::");
+        span.unwrap()
+            .note(format!(
+                "`{}` isn't desugared inside of `{}!` in an iterator item",
+                kind, name,
+            ))
+            .note(format!(
+                "that's fine if `{}!` handles it itself, like a nested generator macro does, \
+                 but it won't compile if it passes it through to its expansion",
+                name,
+            ))
+            .help(format!(
+                "only the arguments of well known macros like `assert!`, `vec!`, `join!` or \
+                 `select!` are desugared; move the `{}` out of the `{}!` call and into a binding",
+                kind, name,
+            ))
+            .emit();
+    }
+}
+
+fn find_desugared_token(tokens: TokenStream, is_async: bool) -> Option<(Span, &'static str)> {
+    let mut after_dot = false;
+    for tt in tokens {
+        let is_dot = matches!(&tt, TokenTree::Punct(p) if p.as_char() == '.');
+        match tt {
+            TokenTree::Ident(ident) if is_async && after_dot && ident == "await" => {
+                return Some((ident.span(), ".await"));
+            }
+            TokenTree::Ident(ident) if ident == "yield" => return Some((ident.span(), "yield")),
+            TokenTree::Group(group) => {
+                if let Some(found) = find_desugared_token(group.stream(), is_async) {
+                    return Some(found);
+                }
+            }
+            _ => {}
+        }
+        after_dot = is_dot;
+    }
+    None
+}
@@ -0,0 +1,140 @@
+//! Inference of the `size_hint` of iterator items with straightforward bodies.
+//!
+//! We count the `yield`s that always run, and for `for` loops over arguments that aren't used
+//! anywhere else, the items of the argument times the `yield`s in the body of the loop. The
+//! argument's `size_hint` is taken before the generator is built, see
+//! `iterator_item::size_hint_of!`. For anything else, we can't tell the number of items from the
+//! syntax alone, and fall back to `(0, None)`.
+
+use proc_macro2::{TokenStream, TokenTree};
+use quote::quote;
+use syn::*;
+
+use crate::yield_from;
+
+/// What a statement in the body of an iterator item adds to its `size_hint`.
+enum Count {
+    /// It always yields this many items.
+    Fixed(usize),
+    /// `for pat in arg { .. }`, yielding this many items for every item of `arg`.
+    Loop(Ident, usize),
+}
+
+/// The expression computing the `size_hint` of an iterator item with `body`, if it is simple
+/// enough, and whether it is exact. It is when it only counts `yield`s that always run: the
+/// `size_hint` of an argument doesn't have to be. `args` are the bindings of its arguments.
+pub fn infer(body: &Block, args: &[Ident]) -> Option<(TokenStream, bool)> {
+    let tokens = quote!(#body);
+    let mut fixed = 0;
+    let mut loops = vec![];
+    for stmt in &body.stmts {
+        match count(stmt, args, &tokens)? {
+            Count::Fixed(n) => fixed += n,
+            Count::Loop(arg, n) => loops.push((arg, n)),
+        }
+    }
+    let mut size_hint = quote!((#fixed, ::core::option::Option::Some(#fixed)));
+    let exact = loops.is_empty();
+    for (arg, n) in loops {
+        size_hint = quote! {
+            ::iterator_item::__internal::add_size_hints(
+                #size_hint,
+                ::iterator_item::__internal::scale_size_hint(
+                    iterator_item::size_hint_of!(#arg),
+                    #n,
+                ),
+            )
+        };
+    }
+    Some((size_hint, exact))
+}
+
+/// The bindings of the arguments with patterns `pats` that `infer` can use: plain identifiers
+/// bound by value.
+pub fn bindings<'a>(pats: impl Iterator<Item = &'a Pat>) -> Vec<Ident> {
+    pats.filter_map(|pat| match pat {
+        Pat::Ident(PatIdent {
+            by_ref: None,
+            subpat: None,
+            ident,
+            ..
+        }) => Some(ident.clone()),
+        _ => None,
+    })
+    .collect()
+}
+
+fn count(stmt: &Stmt, args: &[Ident], body: &TokenStream) -> Option<Count> {
+    let expr = match stmt {
+        Stmt::Semi(expr, _) | Stmt::Expr(expr) => expr,
+        Stmt::Local(_) | Stmt::Item(_) => return is_plain(stmt).then_some(Count::Fixed(0)),
+    };
+    match expr {
+        Expr::Yield(_) => yields(stmt).map(Count::Fixed),
+        Expr::ForLoop(for_loop) if for_loop.attrs.is_empty() => {
+            let arg = match &*for_loop.expr {
+                Expr::Path(path) => path.path.get_ident()?,
+                Expr::Reference(ExprReference {
+                    mutability: None,
+                    expr,
+                    ..
+                }) => match &**expr {
+                    Expr::Path(path) => path.path.get_ident()?,
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            // If the argument is used anywhere else, it could have been advanced or replaced.
+            if !args.contains(arg) || occurrences(body.clone(), arg) != 1 {
+                return None;
+            }
+            let mut n = 0;
+            for stmt in &for_loop.body.stmts {
+                n += yields(stmt)?;
+            }
+            Some(Count::Loop(arg.clone(), n))
+        }
+        _ => is_plain(stmt).then_some(Count::Fixed(0)),
+    }
+}
+
+/// How many items `stmt` yields: 1 for `yield expr;`, 0 for statements that can't yield or exit
+/// early, and `None` for everything else.
+fn yields(stmt: &Stmt) -> Option<usize> {
+    match stmt {
+        Stmt::Semi(Expr::Yield(expr), _) | Stmt::Expr(Expr::Yield(expr)) => {
+            if yield_from::is_marked(expr) {
+                return None;
+            }
+            is_plain(&expr.expr).then_some(1)
+        }
+        stmt => is_plain(stmt).then_some(0),
+    }
+}
+
+/// Whether `tokens` can't yield, nor leave the iterator item or the loop it is in early. We look
+/// at the tokens so that we don't miss a `?` or a `return` in the arguments of a macro.
+fn is_plain(tokens: &impl quote::ToTokens) -> bool {
+    fn plain(tokens: TokenStream) -> bool {
+        tokens.into_iter().all(|tt| match tt {
+            TokenTree::Ident(ident) => !["yield", "return", "break", "continue"]
+                .iter()
+                .any(|keyword| ident == keyword),
+            TokenTree::Punct(punct) => punct.as_char() != '?',
+            TokenTree::Group(group) => plain(group.stream()),
+            TokenTree::Literal(_) => true,
+        })
+    }
+    plain(tokens.to_token_stream())
+}
+
+fn occurrences(tokens: TokenStream, ident: &Ident) -> usize {
+    tokens
+        .into_iter()
+        .map(|tt| match tt {
+            TokenTree::Ident(i) if &i == ident => 1,
+            TokenTree::Group(group) => occurrences(group.stream(), ident),
+            _ => 0,
+        })
+        .sum()
+}
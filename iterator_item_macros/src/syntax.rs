@@ -0,0 +1,328 @@
+//! Syntax profiles: which spellings of an iterator item's header are accepted.
+//!
+//! `parse_fn` understands every spelling we know of, and asks the active `Profile` whether the
+//! one it found is allowed. Forks exploring a syntax can add a `Profile` to `PROFILES` instead of
+//! patching the parser. The profile is picked per invocation, with
+//! `iterator_item! { syntax = "gen-arrow"; gen fn foo() -> i32 { .. } }`, or for the whole crate
+//! with one of the `syntax-*` cargo features.
+//!
+//! The first spelling a profile accepts is the one suggested when the header is missing it.
+//!
+//! `gen!` headers have no keyword to introduce them, so only their marker is checked, against the
+//! crate's profile. `#[iterator]` annotates regular function signatures, which no profile covers.
+
+use proc_macro::Span;
+
+// Only one profile can be active for the whole crate, rather than silently picking one.
+#[cfg(any(
+    all(feature = "syntax-star-yields", feature = "syntax-star-arrow"),
+    all(feature = "syntax-star-yields", feature = "syntax-star-yield"),
+    all(feature = "syntax-star-yields", feature = "syntax-gen-arrow"),
+    all(feature = "syntax-star-yields", feature = "syntax-fn-fat-arrow"),
+    all(feature = "syntax-star-arrow", feature = "syntax-star-yield"),
+    all(feature = "syntax-star-arrow", feature = "syntax-gen-arrow"),
+    all(feature = "syntax-star-arrow", feature = "syntax-fn-fat-arrow"),
+    all(feature = "syntax-star-yield", feature = "syntax-gen-arrow"),
+    all(feature = "syntax-star-yield", feature = "syntax-fn-fat-arrow"),
+    all(feature = "syntax-gen-arrow", feature = "syntax-fn-fat-arrow"),
+))]
+compile_error!("only one of the `syntax-*` features of `iterator_item` can be enabled");
+
+/// What a profile does with one spelling.
+#[derive(Clone, Copy)]
+pub enum Rule {
+    Accept,
+    /// Reject the spelling with an error, and optionally a help message.
+    Reject(&'static str, Option<&'static str>),
+}
+
+use Rule::*;
+
+pub struct Profile {
+    pub name: &'static str,
+    /// The keywords that introduce an iterator item: `fn`, `fn*`, `gen fn`, `gen`, `k#gen fn`, etc.
+    pub heads: &'static [(&'static str, Rule)],
+    /// The markers before the type it yields: `yields`, `yield`, `->` and `=>`.
+    pub markers: &'static [(&'static str, Rule)],
+}
+
+const GEN_WITHOUT_FN: Rule = Reject(
+    "`gen` is a qualifier of `fn`",
+    Some("write `gen fn` instead"),
+);
+
+/// Every spelling `parse_fn` knows about, accepted.
+const ALL_HEADS: &[(&str, Rule)] = &[
+    ("fn*", Accept),
+    ("fn", Accept),
+    ("gen", Accept),
+    ("gen fn", Accept),
+    ("r#gen", Accept),
+    ("r#gen fn", Accept),
+    ("k#gen", Accept),
+    ("k#gen fn", Accept),
+    ("generator", Accept),
+    ("generator fn", Accept),
+    ("iterator", Accept),
+    ("iterator fn", Accept),
+];
+const ALL_MARKERS: &[(&str, Rule)] = &[
+    ("yields", Accept),
+    ("yield", Accept),
+    ("->", Accept),
+    ("=>", Accept),
+];
+
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        name: "default",
+        heads: ALL_HEADS,
+        markers: ALL_MARKERS,
+    },
+    Profile {
+        name: "star-yields",
+        heads: &[("fn*", Accept)],
+        markers: &[
+            ("yields", Accept),
+            (
+                "->",
+                Reject(
+                    "`->` is the return type of regular functions",
+                    Some("use `yields` for the type of the values the iterator item yields"),
+                ),
+            ),
+        ],
+    },
+    Profile {
+        name: "star-arrow",
+        heads: &[("fn*", Accept)],
+        markers: &[("->", Accept)],
+    },
+    Profile {
+        name: "star-yield",
+        heads: &[("fn*", Accept)],
+        markers: &[("yield", Accept)],
+    },
+    // RFC 2996
+    Profile {
+        name: "gen-arrow",
+        heads: &[
+            ("gen fn", Accept),
+            ("r#gen fn", Accept),
+            ("k#gen fn", Accept),
+            ("gen", GEN_WITHOUT_FN),
+            ("r#gen", GEN_WITHOUT_FN),
+            ("k#gen", GEN_WITHOUT_FN),
+        ],
+        markers: &[("->", Accept)],
+    },
+    Profile {
+        name: "fn-fat-arrow",
+        heads: &[("fn", Accept)],
+        markers: &[
+            ("=>", Accept),
+            (
+                "->",
+                Reject(
+                    "`->` would make this a regular function",
+                    Some("use `=>` for the type of the values the iterator item yields"),
+                ),
+            ),
+        ],
+    },
+];
+
+impl Profile {
+    pub fn by_name(name: &str) -> Option<&'static Profile> {
+        PROFILES.iter().find(|profile| profile.name == name)
+    }
+
+    /// The profile selected with the `syntax-*` cargo features, or `default`.
+    pub fn from_features() -> &'static Profile {
+        let name = if cfg!(feature = "syntax-star-yields") {
+            "star-yields"
+        } else if cfg!(feature = "syntax-star-arrow") {
+            "star-arrow"
+        } else if cfg!(feature = "syntax-star-yield") {
+            "star-yield"
+        } else if cfg!(feature = "syntax-gen-arrow") {
+            "gen-arrow"
+        } else if cfg!(feature = "syntax-fn-fat-arrow") {
+            "fn-fat-arrow"
+        } else {
+            "default"
+        };
+        Profile::by_name(name).unwrap()
+    }
+
+    /// The heads this profile accepts, for diagnostics.
+    pub fn accepted_heads(&self) -> String {
+        accepted(self.heads)
+    }
+
+    /// The markers this profile accepts, for diagnostics.
+    pub fn accepted_markers(&self) -> String {
+        accepted(self.markers)
+    }
+
+    /// The head to suggest when there is none.
+    pub fn preferred_head(&self) -> &'static str {
+        preferred(self.heads)
+    }
+
+    /// The marker to suggest when there is none.
+    pub fn preferred_marker(&self) -> &'static str {
+        preferred(self.markers)
+    }
+
+    /// The keyword of an accepted head that `word` is likely a typo of, like `gen` for `genn`.
+    pub fn suggest_head_keyword(&self, word: &str) -> Option<&'static str> {
+        let keywords = self.heads.iter().filter_map(|(spelling, rule)| match rule {
+            Accept => spelling.split(' ').next().map(|k| k.trim_end_matches('*')),
+            Reject(..) => None,
+        });
+        closest(word, keywords)
+    }
+
+    /// The accepted marker that `word` is likely a typo of, like `yields` for `yeilds`.
+    pub fn suggest_marker(&self, word: &str) -> Option<&'static str> {
+        let markers = self
+            .markers
+            .iter()
+            .filter_map(|(spelling, rule)| match rule {
+                Accept if spelling.starts_with(char::is_alphabetic) => Some(*spelling),
+                _ => None,
+            });
+        closest(word, markers)
+    }
+
+    pub fn accepts_marker(&self, spelling: &str) -> bool {
+        matches!(
+            self.markers.iter().find(|(s, _)| *s == spelling),
+            Some((_, Accept))
+        )
+    }
+
+    pub fn check_head(&self, spelling: &str, span: Span) {
+        self.check(self.heads, "to identify an iterator item", spelling, span);
+    }
+
+    pub fn check_marker(&self, spelling: &str, span: Span) {
+        self.check(
+            self.markers,
+            "to indicate the value being yielded by the iterator item",
+            spelling,
+            span,
+        );
+    }
+
+    /// Emit the profile's error if it rejects `spelling`, or a generic one if it doesn't list it.
+    fn check(&self, rules: &[(&str, Rule)], role: &str, spelling: &str, span: Span) {
+        match rules.iter().find(|(s, _)| *s == spelling) {
+            Some((_, Accept)) => {}
+            Some((_, Reject(message, help))) => {
+                let diagnostic = span.error(*message);
+                match help {
+                    Some(help) => diagnostic.help(*help).emit(),
+                    None => diagnostic.emit(),
+                }
+            }
+            None => span
+                .error(format!(
+                    "`{}` is not accepted {} in the `{}` syntax profile",
+                    spelling, role, self.name,
+                ))
+                .help(format!("use one of {}", accepted(rules)))
+                .emit(),
+        }
+    }
+}
+
+fn accepted(rules: &[(&str, Rule)]) -> String {
+    rules
+        .iter()
+        .filter(|(_, rule)| matches!(rule, Accept))
+        .map(|(spelling, _)| format!("`{}`", spelling))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn preferred(rules: &[(&'static str, Rule)]) -> &'static str {
+    rules
+        .iter()
+        .find(|(_, rule)| matches!(rule, Accept))
+        .map(|(spelling, _)| *spelling)
+        .expect("syntax profiles accept at least one spelling")
+}
+
+/// The candidate closest to `word`, if it's close enough to be a typo of it. Like `rustc`, we allow
+/// one edit for every three characters.
+fn closest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let max = std::cmp::max(word.chars().count() / 3, 1);
+    candidates
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("yields", "yields"), 0);
+        assert_eq!(edit_distance("yeilds", "yields"), 2);
+        assert_eq!(edit_distance("yield", "yields"), 1);
+        assert_eq!(edit_distance("genn", "gen"), 1);
+        assert_eq!(edit_distance("", "gen"), 3);
+        assert_eq!(edit_distance("gen", ""), 3);
+        assert_eq!(edit_distance("iterator", "generator"), 3);
+    }
+
+    #[test]
+    fn test_closest() {
+        let candidates = || ["gen", "generator", "iterator"].into_iter();
+        assert_eq!(closest("genn", candidates()), Some("gen"));
+        assert_eq!(closest("generater", candidates()), Some("generator"));
+        assert_eq!(closest("itrator", candidates()), Some("iterator"));
+        // One edit is allowed for short words too, but not more.
+        assert_eq!(closest("ge", candidates()), Some("gen"));
+        assert_eq!(closest("foo", candidates()), None);
+        assert_eq!(closest("gen", std::iter::empty()), None);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let default = Profile::by_name("default").unwrap();
+        assert_eq!(default.suggest_marker("yeilds"), Some("yields"));
+        assert_eq!(default.suggest_marker("i32"), None);
+        assert_eq!(default.suggest_head_keyword("genn"), Some("gen"));
+        assert_eq!(default.suggest_head_keyword("fm"), Some("fn"));
+        assert_eq!(default.preferred_head(), "fn*");
+        assert_eq!(default.preferred_marker(), "yields");
+
+        // Rejected spellings are never suggested.
+        let gen_arrow = Profile::by_name("gen-arrow").unwrap();
+        assert_eq!(gen_arrow.suggest_marker("yeilds"), None);
+        assert_eq!(gen_arrow.suggest_head_keyword("genn"), Some("gen"));
+        assert_eq!(gen_arrow.preferred_head(), "gen fn");
+        assert_eq!(gen_arrow.preferred_marker(), "->");
+    }
+}
@@ -0,0 +1,136 @@
+//! Support for `yield from expr`, which yields every item of `expr` in turn.
+//!
+//! `syn` can't parse `yield from expr`, so before parsing we turn it into
+//! `yield __iterator_item_yield_from = expr`, which it can. Assignment has the lowest precedence of
+//! all binary operators, so `expr` is kept whole. `Visitor` then looks for that marker to desugar
+//! the delegation into `iterator_item::gen_yield_from!` or `iterator_item::async_gen_yield_from!`.
+
+use proc_macro2::{Delimiter, Group, Ident, Punct, Spacing, TokenStream, TokenTree};
+use syn::spanned::Spanned;
+use syn::visit_mut::VisitMut;
+use syn::*;
+
+const MARKER: &str = "__iterator_item_yield_from";
+/// Marker for a `yield from` that is the last statement of the iterator item's body.
+const TAIL_MARKER: &str = "__iterator_item_yield_from_tail";
+
+/// Replace every `yield from expr` in `tokens` with `yield __iterator_item_yield_from = expr`.
+///
+/// `from` is not a keyword, so `yield from.0` or `yield from + 1` are expressions using a binding
+/// called `from`. We consider it a delegation when `from` is followed by something that starts an
+/// expression: an identifier, a literal, `&`, `*`, parentheses or brackets. Those are ambiguous,
+/// whitespace doesn't matter: `yield from(x)` and `yield from [1, 2]` delegate, and a call or an
+/// index of `from` needs to be written as `yield (from)(x)` or `yield (from)[i]`, like the bitwise
+/// and needs to be written as `yield (from & x)`.
+pub fn mark(tokens: TokenStream) -> TokenStream {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut output = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            TokenTree::Ident(ident)
+                if ident == "yield"
+                    && matches!(tokens.get(i + 1), Some(TokenTree::Ident(from)) if from == "from")
+                    && starts_delegate(tokens.get(i + 2)) =>
+            {
+                let from = &tokens[i + 1];
+                output.push(tokens[i].clone());
+                output.push(TokenTree::Ident(Ident::new(MARKER, from.span())));
+                let mut eq = Punct::new('=', Spacing::Alone);
+                eq.set_span(from.span());
+                output.push(TokenTree::Punct(eq));
+                i += 2;
+                continue;
+            }
+            TokenTree::Group(group) => {
+                let mut new = Group::new(group.delimiter(), mark(group.stream()));
+                new.set_span(group.span());
+                output.push(TokenTree::Group(new));
+            }
+            tt => output.push(tt.clone()),
+        }
+        i += 1;
+    }
+    output.into_iter().collect()
+}
+
+fn starts_delegate(tt: Option<&TokenTree>) -> bool {
+    match tt {
+        Some(TokenTree::Ident(ident)) => ident != "as",
+        Some(TokenTree::Literal(_)) => true,
+        Some(TokenTree::Punct(punct)) => punct.as_char() == '&' || punct.as_char() == '*',
+        Some(TokenTree::Group(group)) => matches!(
+            group.delimiter(),
+            Delimiter::Parenthesis | Delimiter::Bracket
+        ),
+        _ => false,
+    }
+}
+
+fn marker(expr: &ExprYield) -> Option<&ExprAssign> {
+    match expr.expr.as_deref() {
+        Some(Expr::Assign(assign)) => match &*assign.left {
+            Expr::Path(path) if path.path.is_ident(MARKER) || path.path.is_ident(TAIL_MARKER) => {
+                Some(assign)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether `expr` is a `yield from`.
+pub fn is_marked(expr: &ExprYield) -> bool {
+    marker(expr).is_some()
+}
+
+/// Take the delegate out of a `yield from`, and whether it's the last statement of the body.
+pub fn take(expr: &mut ExprYield) -> (Expr, bool) {
+    let tail = match marker(expr) {
+        Some(assign) => matches!(&*assign.left, Expr::Path(p) if p.path.is_ident(TAIL_MARKER)),
+        None => false,
+    };
+    match expr.expr.take().map(|expr| *expr) {
+        Some(Expr::Assign(assign)) => (*assign.right, tail),
+        _ => unreachable!("`take` is only called for `yield from` expressions"),
+    }
+}
+
+/// If the iterator item ends in `yield from expr;`, nothing else will be yielded after `expr` is
+/// exhausted, so its `size_hint` is the iterator item's. Mark it so that `Visitor` can tell.
+pub fn mark_tail(body: &mut Block) {
+    let expr = match body.stmts.last_mut() {
+        Some(Stmt::Semi(expr, _)) | Some(Stmt::Expr(expr)) => expr,
+        _ => return,
+    };
+    if let Expr::Yield(expr) = expr {
+        if let Some(Expr::Assign(assign)) = expr.expr.as_deref_mut() {
+            if let Expr::Path(path) = &mut *assign.left {
+                if path.path.is_ident(MARKER) {
+                    let span = path.path.span();
+                    path.path = Ident::new(TAIL_MARKER, span).into();
+                }
+            }
+        }
+    }
+}
+
+/// `yield from` inside of closures, `async` blocks and nested items in the body of an iterator
+/// item can't be desugared, they don't `yield` from the iterator item.
+pub struct Nested;
+
+impl VisitMut for Nested {
+    fn visit_expr_mut(&mut self, i: &mut Expr) {
+        syn::visit_mut::visit_expr_mut(self, i);
+        if let Expr::Yield(expr) = i {
+            if is_marked(expr) {
+                expr.yield_token
+                    .span
+                    .unwrap()
+                    .error("`yield from` is only supported directly in the body of iterator items")
+                    .emit();
+                *i = take(expr).0;
+            }
+        }
+    }
+}
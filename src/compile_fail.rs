@@ -0,0 +1,163 @@
+//! Code that must not compile, checked as doctests.
+//!
+//! The macros recover from malformed headers: they report the mistake and carry on as if it had
+//! been fixed, so the body is still checked, like the type mismatch in each of these is.
+//!
+//! A typo of the marker before the type it yields:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator_item;
+//! iterator_item! {
+//!     fn* numbers() yeilds i32 {
+//!         let _: u8 = "";
+//!         yield 1;
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! The type it yields without a marker:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator_item;
+//! iterator_item! {
+//!     fn* numbers() i32 {
+//!         let _: u8 = "";
+//!         yield 1;
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! No keyword to identify the iterator item:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator_item;
+//! iterator_item! {
+//!     numbers() yields i32 {
+//!         let _: u8 = "";
+//!         yield 1;
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! A typo of the keyword:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator_item;
+//! iterator_item! {
+//!     genn fn numbers() -> i32 {
+//!         let _: u8 = "";
+//!         yield 1;
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Something else where the keyword goes:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator_item;
+//! iterator_item! {
+//!     foo fn numbers() -> i32 {
+//!         let _: u8 = "";
+//!         yield 1;
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Iterator items can't be `const`, their body only runs when they are iterated:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator_item;
+//! iterator_item! {
+//!     const fn* numbers() yields i32 {
+//!         let _: u8 = "";
+//!         yield 1;
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! They can't use an ABI other than `Rust` either, the iterator they return can't cross it:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator_item;
+//! iterator_item! {
+//!     extern "C" fn* numbers() yields i32 {
+//!         let _: u8 = "";
+//!         yield 1;
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! `#[exact_size]` needs an exact `size_hint`, the one of an argument might not be:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator_item;
+//! iterator_item! {
+//!     #[exact_size]
+//!     fn* doubled(items: Vec<i32>) yields i32 {
+//!         let _: u8 = "";
+//!         for item in items {
+//!             yield item * 2;
+//!         }
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! And `set_size_hint!` can't change it afterwards:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator_item;
+//! iterator_item! {
+//!     #[size_hint(exact = 2)]
+//!     fn* pair() yields i32 {
+//!         let _: u8 = "";
+//!         yield 1;
+//!         set_size_hint!((2, Some(2)));
+//!         yield 2;
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! `#[iterator]` functions can't return a `DoubleEndedIterator`, they only run from the front:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator;
+//! #[iterator]
+//! fn numbers() -> impl Iterator<Item = i32> + DoubleEndedIterator {
+//!     let _: u8 = "";
+//!     yield 1;
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Nor a trait object, their return type is the one they are written with:
+//!
+//! ```compile_fail,E0308
+//! #![feature(generators, generator_trait)]
+//! # use iterator_item::iterator;
+//! #[iterator]
+//! #[boxed(dyn)]
+//! fn numbers() -> impl Iterator<Item = i32> {
+//!     let _: u8 = "";
+//!     yield 1;
+//! }
+//! # fn main() {}
+//! ```
@@ -6,6 +6,8 @@
 #![cfg_attr(feature = "std_async_iter", async_stream)]
 #![no_std]
 
+extern crate alloc;
+
 /// This macro can be used to make functions that function as generators.
 ///
 /// Functions annotated with this macro can use the `yield` keyword to give the next element in a
@@ -13,10 +15,85 @@
 /// iterator of the type it yields, rather than just one value of that type.
 ///
 /// You can still use the `return` keyword to terminate the generator early, but the `return`
-/// keyword cannot take a value; it only terminates the function.
+/// keyword cannot take a value; it only terminates the function. Unless the generator declares
+/// what it returns with `returns Ty` after the type it yields, in which case the returned value
+/// is available through [`IteratorReturn`] (or [`StreamReturn`] for `async` iterator items) once
+/// it has finished. Without one, the body can't end in a value either:
+///
+/// ```compile_fail,E0308
+/// #![feature(generators, generator_trait)]
+/// # use iterator_item::iterator_item;
+///
+/// iterator_item! {
+///     fn* numbers() yields i32 {
+///         yield 1;
+///         2
+///     }
+/// }
+/// # fn main() {}
+/// ```
 ///
 /// The behavior of `?` is also modified in these functions. In the event of an error, the
-/// generator yields the error value, and then the next time it is resumed it returns `None`.
+/// generator yields the error value, and then the next time it is resumed it returns `None`. In
+/// generators with a `returns` type, `?` returns the error like it would in a regular function.
+/// That's for iterator items that yield a `Result` or an `Option`, like `io::Result<T>`. For
+/// other types `?` stops the iterator item without yielding anything. Type aliases and custom `Try`
+/// types can't be told apart from the rest, so `?` warns in types whose name ends in `Result` or
+/// `Option`, like `ParseResult<T>`, unless the iterator item picks one behavior with `#[try_yield]`
+/// or `#[try_stop]`.
+///
+/// The returned iterators are fused: once they return `None` they will keep returning `None`, and
+/// they implement `FusedIterator` (or `FusedStream` for `async` iterator items).
+///
+/// `async` iterator items can consume other `Stream`s with `for await pat in stream { .. }` loops,
+/// which support `break`, `continue` and labels like regular `for` loops do.
+///
+/// Generators can also be trait methods, with or without a default body. Their return type is an
+/// `impl Trait`, which needs `return_position_impl_trait_in_trait`, unless they are annotated with
+/// `#[boxed(dyn)]` (or `#[boxed(dyn + Send)]`, etc.) to return a boxed trait object instead,
+/// keeping the trait dyn compatible. Like `#[boxed]`, it lets the body hold borrows across
+/// `yield`s.
+///
+/// Elided lifetimes in the types an iterator item yields and returns follow the rules of the
+/// return type of regular functions: `fn* iter(&self) yields &T` borrows from `self`, and without
+/// `&self` they are the only lifetime in the arguments. Otherwise they have to be named. The
+/// returned iterator can borrow from all of the arguments, even when their lifetimes are unrelated,
+/// like in `fn* zip(a: &[u8], b: &[&str]) yields (u8, usize)`.
+///
+/// Methods can take `self` with an explicit type too, like `self: Box<Self>`, `self: Rc<Self>` or
+/// `self: Pin<&mut Self>`. A reference to `Self` in it lends its lifetime to the yielded type like
+/// `&self` does.
+///
+/// Iterator items can be `unsafe`, which makes their whole body an unsafe context. The callers'
+/// obligations then hold for as long as the returned iterator is used, not only during the call.
+/// They can also be `default` in specializing impls and `extern "Rust"`, but they can't be `const`
+/// or use other ABIs.
+///
+/// With `receives Ty` after the type it yields, the generator becomes a [`Coroutine`] instead of
+/// an iterator: it gets passed a value every time it is resumed, which is what `yield` evaluates
+/// to.
+///
+/// `yield from iter` yields every item of an `IntoIterator` (or of a `Stream` in `async` iterator
+/// items) before continuing. Unless the iterator item has an explicit `#[size_hint]`, its
+/// `size_hint` follows the delegate's while it is being consumed.
+/// `from` followed by parentheses or brackets always delegates, so calling a function called
+/// `from` needs parentheses around it: `yield (from)(x)`.
+///
+/// Without a `#[size_hint(..)]`, the `size_hint` is inferred for bodies made of `yield`s that
+/// always run and of `for` loops over arguments that aren't used anywhere else, whose bodies only
+/// `yield` unconditionally. The `size_hint` of those arguments is taken before anything runs. Any
+/// other body gets `(0, None)`, which is also what `#[no_size_hint]` keeps.
+///
+/// `#[exact_size]` makes the returned iterator an `ExactSizeIterator`, which needs a `size_hint`
+/// that is exact from the start: `#[size_hint(exact = len)]`, or an inferred one when the body
+/// always yields the same number of items. `set_size_hint!` can't change it afterwards.
+/// `#[iterator]` functions that return an `impl ExactSizeIterator` get it implicitly. In debug
+/// builds, iterator items panic when they yield more or fewer items than the `size_hint` they
+/// declare allows. Inferred ones aren't checked, the arguments they come from can be wrong.
+///
+/// Functions and methods, including trait methods, can also be annotated with `#[iterator]`
+/// instead, keeping a regular signature that returns an `impl Iterator<Item = Ty>` (or an
+/// `impl Stream<Item = Ty>` if they are `async`).
 ///
 /// ## Forbidding self-references
 ///
@@ -25,6 +102,10 @@
 /// should move out of the state of the generator, taking it as an argument, or else not holding it
 /// by reference across a point that you yield.
 ///
+/// Generators declared with `static fn*` (or annotated with `#[boxed]`) lift this restriction, at
+/// the cost of an allocation: they are pinned on the heap, so they can hold references to their own
+/// locals across `yield`s while still being `Unpin` iterators.
+///
 /// ## Unstable features
 ///
 /// In order to use this attribute, you must turn on all of these features:
@@ -73,34 +154,290 @@
 ///
 /// The intention of this crate is for people to fork it and submit alternative syntax for this
 /// feature that they believe would make for a better user experience.
+///
+/// ## Syntax profiles
+///
+/// Every spelling above is accepted by default. A syntax profile restricts them to a single
+/// proposal, like `gen-arrow` for `gen fn foo() -> i32`. It is picked for a single invocation by
+/// starting it with `syntax = "gen-arrow";`, or for the whole crate with the `syntax-gen-arrow`
+/// cargo feature, only one of which can be enabled. The profiles are listed in
+/// `iterator_item_macros/src/syntax.rs`, and forks can add their own there. The crate's profile
+/// also applies to the type `gen!` blocks yield, but not to `#[iterator]`, which annotates
+/// regular functions.
+///
+/// The profiles that accept `r#gen fn` also accept `k#gen fn`, but only in crates on the 2018
+/// edition. From the 2021 edition on, `k#gen` is a reserved prefix that never reaches the macro.
+///
+/// ```rust,edition2018
+/// #![feature(generators, generator_trait)]
+/// # use iterator_item::iterator_item;
+///
+/// iterator_item! {
+///     syntax = "gen-arrow";
+///     k#gen fn numbers() -> i32 {
+///         yield 1;
+///     }
+/// }
+///
+/// fn main() {
+///     assert_eq!(numbers().collect::<Vec<_>>(), vec![1]);
+/// }
+/// ```
 pub use iterator_item_macros::{iterator, iterator_item};
 
+/// An anonymous iterator item, as an expression.
+///
+/// The body is desugared like the body of a `fn*`, but instead of taking arguments it captures its
+/// environment like a `move` closure would. `gen! { .. }` evaluates to an `Iterator`, and
+/// `gen! { async { .. } }` to a `Stream`.
+///
+/// The body can be preceded by `#[size_hint(..)]`, `static` (or `#[boxed]`), `async` and the
+/// type it yields, which is needed for `?` to yield errors instead of returning:
+/// `gen! { async yields Result<i32, Error> { .. } }`.
+///
+/// With closure arguments before the type it yields, `gen!(|x: i32| yields i32 { .. })` is a
+/// closure that returns a new iterator item when it is called. The iterator item takes what the
+/// closure captures, so it is `FnOnce` unless it only captures references and `Copy` values. With
+/// `#[clone_captures]` before the arguments, each call gets its own clone of the captures instead,
+/// so they have to be `Clone`. Elided lifetimes in the types of its arguments all get the same name, and are bound
+/// with `for<..>` on the closure, which needs the `closure_lifetime_binder` feature. Then the
+/// closure returns a boxed trait object bounded by that lifetime, so it needs the type it yields.
+///
+/// ```rust
+/// #![feature(generators, generator_trait)]
+/// # use iterator_item::gen;
+///
+/// fn main() {
+///     let words = vec!["a", "b"];
+///     let mut shouted = gen! {
+///         for word in words {
+///             yield word.to_uppercase();
+///         }
+///     };
+///     assert_eq!(shouted.next(), Some("A".to_string()));
+///     assert_eq!(shouted.next(), Some("B".to_string()));
+///     assert_eq!(shouted.next(), None);
+/// }
+/// ```
+pub use iterator_item_macros::gen;
+
+/// Set the `size_hint` of the iterator item whose body it is in.
+///
+/// The `size_hint` counts the items still to come, and it shrinks by one with every item yielded
+/// afterwards, so it only needs to be set again when the iterator item learns something new:
+///
+/// ```rust
+/// #![feature(generators, generator_trait)]
+/// # use iterator_item::{iterator_item, set_size_hint};
+///
+/// iterator_item! {
+///     fn* words(text: String) yields String {
+///         let words: Vec<String> = text.split(' ').map(String::from).collect();
+///         set_size_hint!((words.len(), Some(words.len())));
+///         for word in words {
+///             yield word;
+///         }
+///     }
+/// }
+///
+/// fn main() {
+///     let mut words = words("a b".to_string());
+///     assert_eq!(words.size_hint(), (0, None));
+///     assert_eq!(words.next(), Some("a".to_string()));
+///     assert_eq!(words.size_hint(), (1, Some(1)));
+/// }
+/// ```
+///
+/// It is rewritten by the iterator item's desugaring, using it anywhere else is an error.
+#[macro_export]
+macro_rules! set_size_hint {
+    ($($tokens:tt)*) => {
+        compile_error!("`set_size_hint!` can only be used in the body of an iterator item")
+    };
+}
+
+/// An iterator that produces a final value once it is exhausted.
+///
+/// The iterator items declared with `returns Ty` implement it:
+///
+/// ```rust
+/// #![feature(generators, generator_trait)]
+/// # use iterator_item::{iterator_item, IteratorReturn};
+///
+/// iterator_item! {
+///     fn* evens(input: Vec<i32>) yields i32 returns usize {
+///         let mut odd = 0;
+///         for x in input {
+///             if x % 2 == 0 {
+///                 yield x;
+///             } else {
+///                 odd += 1;
+///             }
+///         }
+///         odd
+///     }
+/// }
+///
+/// fn main() {
+///     let mut evens = evens(vec![1, 2, 3, 4, 5]);
+///     assert_eq!(evens.next(), Some(2));
+///     assert_eq!(evens.take_return(), None);
+///     assert_eq!(evens.finish(), 3);
+/// }
+/// ```
+pub trait IteratorReturn: Iterator {
+    type Return;
+
+    /// Take the value the iterator returned. This is `None` until `next` has returned `None`, and
+    /// after it has already been taken.
+    fn take_return(&mut self) -> Option<Self::Return>;
+
+    /// Exhaust the iterator, dropping its remaining items, and get the value it returned.
+    ///
+    /// Panics if the returned value was already taken.
+    fn finish(mut self) -> Self::Return
+    where
+        Self: Sized,
+    {
+        for _ in &mut self {}
+        self.take_return()
+            .expect("the value returned by the iterator was already taken")
+    }
+}
+
+/// A `Stream` that produces a final value once it is exhausted, like the `async` iterator items
+/// declared with `returns Ty`.
+pub trait StreamReturn {
+    type Return;
+
+    /// Take the value the stream returned. This is `None` until `poll_next` has returned
+    /// `Poll::Ready(None)`, and after it has already been taken.
+    fn take_return(self: core::pin::Pin<&mut Self>) -> Option<Self::Return>;
+}
+
+/// What a [`Coroutine`] did when it was resumed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CoroutineState<Y, R> {
+    /// It yielded a value, and it can be resumed again.
+    Yielded(Y),
+    /// It finished, returning a value. It can't be resumed again.
+    Complete(R),
+}
+
+/// A coroutine item: an iterator item that receives a value every time it is resumed, declared
+/// with `receives Ty` or `receives pat: Ty`.
+///
+/// The value passed to the first `resume` call is bound to `pat` (or dropped if there's no
+/// `pat`), and each `yield` evaluates to the value passed to the `resume` call that follows it.
+/// Elided lifetimes in the received type are all the same lifetime parameter of the coroutine item,
+/// so the references it receives have to outlive it.
+///
+/// ```rust
+/// #![feature(generators, generator_trait)]
+/// # use iterator_item::{iterator_item, Coroutine, CoroutineState};
+///
+/// iterator_item! {
+///     fn* running_sum() yields i32 receives mut x: i32 returns i32 {
+///         let mut sum = 0;
+///         while x != 0 {
+///             sum += x;
+///             x = yield sum;
+///         }
+///         sum
+///     }
+/// }
+///
+/// fn main() {
+///     let mut sum = running_sum();
+///     assert_eq!(sum.resume(1), CoroutineState::Yielded(1));
+///     assert_eq!(sum.resume(2), CoroutineState::Yielded(3));
+///     assert_eq!(sum.resume(0), CoroutineState::Complete(3));
+/// }
+/// ```
+pub trait Coroutine<A> {
+    type Yield;
+    type Return;
+
+    /// Resume the coroutine with `arg`, running it until its next `yield` or until it finishes.
+    ///
+    /// Panics if the coroutine has already finished.
+    fn resume(&mut self, arg: A) -> CoroutineState<Self::Yield, Self::Return>;
+}
+
 #[doc(hidden)]
 pub mod __internal {
+    use core::future::Future;
+    use core::iter::FusedIterator;
     use core::marker::Unpin;
     use core::ops::{Generator, GeneratorState};
     use core::pin::Pin;
+    use core::ptr::NonNull;
     use core::task::{Context, Poll};
+
+    use crate::{IteratorReturn, StreamReturn};
+    pub use alloc::boxed::Box;
     #[cfg(not(feature = "std_async_iter"))]
-    pub use futures::stream::{Stream, StreamExt};
+    pub use futures::stream::{FusedStream, Stream, StreamExt};
+
+    /// What the `Generator` backing an iterator item yields: either its next item, or a new value
+    /// for its `size_hint`, which is how `yield from` forwards the `size_hint` of its delegate and
+    /// how `set_size_hint!` refines it. The new value counts the items still to come.
+    pub enum GenYield<T> {
+        Item(T),
+        SizeHint((usize, Option<usize>)),
+    }
 
     /// New-type wrapper around the unstable `Generator` opaque type.
     ///
     /// The final version of this type in `std`, if needed, would *also* not be be either
     /// perma-unstable to use directly, or another opaque type. This is used to both give us a way
     /// to `impl Iterator` and somewhere to hold the computed `size_hint` value.
-    pub struct IteratorItem<G: Generator<Return = ()> + Unpin> {
+    ///
+    /// `complete` keeps track of whether the underlying `Generator` has finished, because resuming
+    /// it after that point panics. What it returned is kept in `return_value` until it's taken.
+    ///
+    /// `size_hint` shrinks by one with every item, and is `(0, Some(0))` once it has finished.
+    /// When it was declared with `#[size_hint]`, `checked` is set, and in debug builds the number
+    /// of items it yields is checked against it. A panic message names the iterator item with
+    /// `name`.
+    pub struct IteratorItem<G: Generator + Unpin> {
         pub gen: G,
+        pub name: &'static str,
         pub size_hint: (usize, Option<usize>),
+        pub checked: bool,
+        pub yielded: usize,
+        pub complete: bool,
+        pub return_value: Option<G::Return>,
     }
 
-    impl<G: Generator<Return = ()> + Unpin> Iterator for IteratorItem<G> {
-        type Item = G::Yield;
+    impl<G: Generator<Yield = GenYield<T>> + Unpin, T> Iterator for IteratorItem<G> {
+        type Item = T;
 
         fn next(&mut self) -> Option<Self::Item> {
-            match Pin::new(&mut self.gen).resume(()) {
-                GeneratorState::Yielded(item) => Some(item),
-                GeneratorState::Complete(()) => None,
+            if self.complete {
+                return None;
+            }
+            loop {
+                match Pin::new(&mut self.gen).resume(()) {
+                    GeneratorState::Yielded(GenYield::Item(item)) => {
+                        count_item(
+                            self.name,
+                            self.checked,
+                            &mut self.yielded,
+                            &mut self.size_hint,
+                        );
+                        return Some(item);
+                    }
+                    GeneratorState::Yielded(GenYield::SizeHint(size_hint)) => {
+                        self.size_hint = size_hint;
+                    }
+                    GeneratorState::Complete(return_value) => {
+                        self.complete = true;
+                        count_complete(self.name, self.checked, self.yielded, &mut self.size_hint);
+                        self.return_value = Some(return_value);
+                        return None;
+                    }
+                }
             }
         }
 
@@ -109,6 +446,120 @@ pub mod __internal {
         }
     }
 
+    impl<G: Generator<Yield = GenYield<T>> + Unpin, T> FusedIterator for IteratorItem<G> {}
+
+    impl<G: Generator<Yield = GenYield<T>> + Unpin, T> IteratorReturn for IteratorItem<G> {
+        type Return = G::Return;
+
+        fn take_return(&mut self) -> Option<Self::Return> {
+            self.return_value.take()
+        }
+    }
+
+    /// An `IteratorItem` that is also an `ExactSizeIterator`, for iterator items with
+    /// `#[exact_size]`. `len` panics if the `size_hint` isn't exact.
+    pub struct ExactSizeIteratorItem<G: Generator + Unpin>(pub IteratorItem<G>);
+
+    impl<G: Generator<Yield = GenYield<T>> + Unpin, T> Iterator for ExactSizeIteratorItem<G> {
+        type Item = T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.0.next()
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.0.size_hint()
+        }
+    }
+
+    impl<G: Generator<Yield = GenYield<T>> + Unpin, T> ExactSizeIterator for ExactSizeIteratorItem<G> {}
+
+    impl<G: Generator<Yield = GenYield<T>> + Unpin, T> FusedIterator for ExactSizeIteratorItem<G> {}
+
+    impl<G: Generator<Yield = GenYield<T>> + Unpin, T> IteratorReturn for ExactSizeIteratorItem<G> {
+        type Return = G::Return;
+
+        fn take_return(&mut self) -> Option<Self::Return> {
+            self.0.take_return()
+        }
+    }
+
+    /// New-type wrapper around the unstable `Generator` opaque type, for iterator items that
+    /// receive values when resumed. Unlike `Generator`, it is stable to use through `Coroutine`.
+    pub struct CoroutineItem<G> {
+        pub gen: G,
+        pub complete: bool,
+    }
+
+    impl<A, G: Generator<A, Yield = GenYield<T>> + Unpin, T> crate::Coroutine<A> for CoroutineItem<G> {
+        type Yield = T;
+        type Return = G::Return;
+
+        fn resume(&mut self, arg: A) -> crate::CoroutineState<T, G::Return> {
+            assert!(!self.complete, "coroutine item resumed after completion");
+            match Pin::new(&mut self.gen).resume(arg) {
+                GeneratorState::Yielded(GenYield::Item(item)) => {
+                    crate::CoroutineState::Yielded(item)
+                }
+                GeneratorState::Yielded(GenYield::SizeHint(_)) => {
+                    unreachable!("coroutine items don't track their `size_hint`")
+                }
+                GeneratorState::Complete(return_value) => {
+                    self.complete = true;
+                    crate::CoroutineState::Complete(return_value)
+                }
+            }
+        }
+    }
+
+    /// The resume argument of the `Generator` backing an `AsyncIteratorItem`: the `Context` of the
+    /// `poll_next` call that resumed it.
+    ///
+    /// We can't name the lifetime of the `Context` in the type of the `Generator`, so we have to
+    /// hold on to it as a pointer. Unlike a `*mut ()`, this is `Send + Sync`, so having it live
+    /// across every `yield` point doesn't make the iterator item `!Send`.
+    pub struct StreamCtx(NonNull<Context<'static>>);
+
+    // SAFETY: the `Context` is only ever accessed during the `poll_next` call that created the
+    // `StreamCtx`, from the thread that is running that call.
+    unsafe impl Send for StreamCtx {}
+    unsafe impl Sync for StreamCtx {}
+
+    impl StreamCtx {
+        fn new(ctx: &mut Context<'_>) -> Self {
+            StreamCtx(NonNull::from(ctx).cast())
+        }
+
+        /// Poll `future` with the `Context` of the ongoing `poll_next` call.
+        pub fn poll<F: Future>(&mut self, future: Pin<&mut F>) -> Poll<F::Output> {
+            // SAFETY: a `StreamCtx` is only created in `poll_next` and handed to the `Generator`
+            // as its resume argument. `async_gen_await!` and `async_gen_yield!` replace the one
+            // they have every time the `Generator` is resumed, so this always points to the
+            // `Context` of the ongoing `poll_next` call.
+            future.poll(unsafe { self.0.as_mut() })
+        }
+
+        /// Poll `stream` for its next item with the `Context` of the ongoing `poll_next` call.
+        #[cfg(not(feature = "std_async_iter"))]
+        pub fn poll_next<S: Stream + ?Sized>(
+            &mut self,
+            stream: Pin<&mut S>,
+        ) -> Poll<Option<S::Item>> {
+            // SAFETY: see `StreamCtx::poll`.
+            stream.poll_next(unsafe { self.0.as_mut() })
+        }
+
+        /// Poll `stream` for its next item with the `Context` of the ongoing `poll_next` call.
+        #[cfg(feature = "std_async_iter")]
+        pub fn poll_next<S: core::stream::Stream + ?Sized>(
+            &mut self,
+            stream: Pin<&mut S>,
+        ) -> Poll<Option<S::Item>> {
+            // SAFETY: see `StreamCtx::poll`.
+            stream.poll_next(unsafe { self.0.as_mut() })
+        }
+    }
+
     /// New-type wrapper around the unstable `Generator` opaque type.
     ///
     /// The final version of this type in `std`, if needed, would *also* not be be either
@@ -117,29 +568,66 @@ pub mod __internal {
     ///
     /// I refer to it as `AsyncIteratorItem` instead of `StreamItem` in anticipation of the trait
     /// potentially being renamed.
-    pub struct AsyncIteratorItem<G: Generator<*mut (), Return = ()>> {
+    pub struct AsyncIteratorItem<G: Generator<StreamCtx>> {
         pub gen: G,
+        pub name: &'static str,
         pub size_hint: (usize, Option<usize>),
+        pub checked: bool,
+        pub yielded: usize,
+        pub complete: bool,
+        pub return_value: Option<G::Return>,
+    }
+
+    impl<G: Generator<StreamCtx>> AsyncIteratorItem<G> {
+        /// Resume the underlying `Generator` unless it has already completed.
+        fn poll_gen<T>(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<T>>
+        where
+            G: Generator<StreamCtx, Yield = Poll<GenYield<T>>>,
+        {
+            // SAFETY: `gen` is never moved out of `self`, and `complete` and `return_value` are not
+            // structurally pinned.
+            let this = unsafe { self.get_unchecked_mut() };
+            if this.complete {
+                return Poll::Ready(None);
+            }
+            loop {
+                let gen: Pin<&mut G> = unsafe { Pin::new_unchecked(&mut this.gen) };
+                match gen.resume(StreamCtx::new(ctx)) {
+                    GeneratorState::Yielded(Poll::Ready(GenYield::Item(item))) => {
+                        count_item(
+                            this.name,
+                            this.checked,
+                            &mut this.yielded,
+                            &mut this.size_hint,
+                        );
+                        return Poll::Ready(Some(item));
+                    }
+                    GeneratorState::Yielded(Poll::Ready(GenYield::SizeHint(size_hint))) => {
+                        this.size_hint = size_hint;
+                    }
+                    GeneratorState::Yielded(Poll::Pending) => return Poll::Pending,
+                    GeneratorState::Complete(return_value) => {
+                        this.complete = true;
+                        count_complete(this.name, this.checked, this.yielded, &mut this.size_hint);
+                        this.return_value = Some(return_value);
+                        return Poll::Ready(None);
+                    }
+                }
+            }
+        }
     }
 
     /// This implementation is functional, but [`Stream` is currently in flux][1]:
     ///
     /// [1]: https://rust-lang.github.io/wg-async-foundations/vision/roadmap/async_iter/traits.html
     #[cfg(feature = "std_async_iter")]
-    impl<G: Generator<*mut (), Yield = Poll<T>, Return = ()>, T> core::stream::Stream
+    impl<G: Generator<StreamCtx, Yield = Poll<GenYield<T>>>, T> core::stream::Stream
         for AsyncIteratorItem<G>
     {
         type Item = T;
 
         fn poll_next(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-            let ctx: *mut () = ctx as *mut Context<'_> as *mut ();
-
-            let gen: Pin<&mut G> = unsafe { Pin::map_unchecked_mut(self, |this| &mut this.gen) };
-            match gen.resume(ctx) {
-                GeneratorState::Yielded(Poll::Ready(item)) => Poll::Ready(Some(item)),
-                GeneratorState::Yielded(Poll::Pending) => Poll::Pending,
-                GeneratorState::Complete(()) => Poll::Ready(None),
-            }
+            self.poll_gen(ctx)
         }
 
         fn size_hint(&self) -> (usize, Option<usize>) {
@@ -148,18 +636,11 @@ pub mod __internal {
     }
 
     #[cfg(not(feature = "std_async_iter"))]
-    impl<G: Generator<*mut (), Yield = Poll<T>, Return = ()>, T> Stream for AsyncIteratorItem<G> {
+    impl<G: Generator<StreamCtx, Yield = Poll<GenYield<T>>>, T> Stream for AsyncIteratorItem<G> {
         type Item = T;
 
         fn poll_next(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-            let ctx: *mut () = ctx as *mut Context<'_> as *mut ();
-
-            let gen: Pin<&mut G> = unsafe { Pin::map_unchecked_mut(self, |this| &mut this.gen) };
-            match gen.resume(ctx) {
-                GeneratorState::Yielded(Poll::Ready(item)) => Poll::Ready(Some(item)),
-                GeneratorState::Yielded(Poll::Pending) => Poll::Pending,
-                GeneratorState::Complete(()) => Poll::Ready(None),
-            }
+            self.poll_gen(ctx)
         }
 
         fn size_hint(&self) -> (usize, Option<usize>) {
@@ -167,6 +648,177 @@ pub mod __internal {
         }
     }
 
+    #[cfg(not(feature = "std_async_iter"))]
+    impl<G: Generator<StreamCtx, Yield = Poll<GenYield<T>>>, T> FusedStream for AsyncIteratorItem<G> {
+        fn is_terminated(&self) -> bool {
+            self.complete
+        }
+    }
+
+    /// The `size_hint` of an iterator item after it yields one more item.
+    fn consume_size_hint(size_hint: (usize, Option<usize>)) -> (usize, Option<usize>) {
+        (
+            size_hint.0.saturating_sub(1),
+            size_hint.1.map(|upper| upper.saturating_sub(1)),
+        )
+    }
+
+    /// Account for one more item of the iterator item `name`, after it already yielded `yielded`.
+    /// In debug builds, panic if its `size_hint` is `checked` and didn't allow for it.
+    fn count_item(
+        name: &str,
+        checked: bool,
+        yielded: &mut usize,
+        size_hint: &mut (usize, Option<usize>),
+    ) {
+        if cfg!(debug_assertions) && checked && size_hint.1 == Some(0) {
+            panic!(
+                "{} yielded more items than its `size_hint` allowed: at most {}, but it yielded {}",
+                name,
+                *yielded,
+                *yielded + 1,
+            );
+        }
+        *yielded += 1;
+        *size_hint = consume_size_hint(*size_hint);
+    }
+
+    /// The iterator item `name` finished after yielding `yielded` items. In debug builds, panic if
+    /// its `size_hint` is `checked` and promised more.
+    fn count_complete(
+        name: &str,
+        checked: bool,
+        yielded: usize,
+        size_hint: &mut (usize, Option<usize>),
+    ) {
+        if cfg!(debug_assertions) && checked && size_hint.0 > 0 {
+            panic!(
+                "{} yielded fewer items than its `size_hint` promised: at least {}, but it yielded \
+                 {}",
+                name,
+                yielded.saturating_add(size_hint.0),
+                yielded,
+            );
+        }
+        *size_hint = (0, Some(0));
+    }
+
+    /// The `size_hint` of an iterator item while it is forwarding the items of a `yield from`
+    /// delegate, from the delegate's after taking the item about to be yielded, which still
+    /// counts. Unless the `yield from` is the last thing the iterator item does, more items can
+    /// come after the delegate's, so only the lower bound carries over.
+    pub fn delegate_size_hint(
+        size_hint: (usize, Option<usize>),
+        tail: bool,
+    ) -> (usize, Option<usize>) {
+        let upper = match (tail, size_hint.1) {
+            (true, Some(upper)) => upper.checked_add(1),
+            _ => None,
+        };
+        (size_hint.0.saturating_add(1), upper)
+    }
+
+    /// An argument of an iterator item, borrowed to take its `size_hint` before the generator is
+    /// built. See `size_hint_of!` for how it's picked.
+    pub struct SizeHintProbe<'a, T>(pub &'a T);
+
+    pub trait IteratorSizeHint {
+        fn size_hint_of(&self) -> (usize, Option<usize>);
+    }
+
+    impl<T: Iterator> IteratorSizeHint for &&&SizeHintProbe<'_, T> {
+        fn size_hint_of(&self) -> (usize, Option<usize>) {
+            self.0.size_hint()
+        }
+    }
+
+    /// Shared references, and other `Copy` collections, can be iterated without consuming the
+    /// argument.
+    pub trait CopySizeHint {
+        fn size_hint_of(&self) -> (usize, Option<usize>);
+    }
+
+    impl<T: IntoIterator + Copy> CopySizeHint for &&SizeHintProbe<'_, T> {
+        fn size_hint_of(&self) -> (usize, Option<usize>) {
+            (*self.0).into_iter().size_hint()
+        }
+    }
+
+    /// Collections that are iterated by value yield as many items as when they are iterated by
+    /// reference.
+    pub trait RefSizeHint {
+        fn size_hint_of(&self) -> (usize, Option<usize>);
+    }
+
+    impl<'a, T> RefSizeHint for &SizeHintProbe<'a, T>
+    where
+        &'a T: IntoIterator,
+    {
+        fn size_hint_of(&self) -> (usize, Option<usize>) {
+            self.0.into_iter().size_hint()
+        }
+    }
+
+    pub trait UnknownSizeHint {
+        fn size_hint_of(&self) -> (usize, Option<usize>);
+    }
+
+    impl<T> UnknownSizeHint for SizeHintProbe<'_, T> {
+        fn size_hint_of(&self) -> (usize, Option<usize>) {
+            (0, None)
+        }
+    }
+
+    /// Names a lifetime that the hidden type of an iterator item's `impl Trait` captures. Unlike
+    /// `+ 'a`, it doesn't require the hidden type to outlive `'a`, so an iterator item can
+    /// capture several unrelated lifetimes.
+    pub trait Captures<'a> {}
+
+    impl<'a, T: ?Sized> Captures<'a> for T {}
+
+    /// The `size_hint` of yielding the items of two iterators, one after the other.
+    pub fn add_size_hints(
+        a: (usize, Option<usize>),
+        b: (usize, Option<usize>),
+    ) -> (usize, Option<usize>) {
+        let upper = match (a.1, b.1) {
+            (Some(a), Some(b)) => a.checked_add(b),
+            _ => None,
+        };
+        (a.0.saturating_add(b.0), upper)
+    }
+
+    /// The `size_hint` of yielding `n` items for every item of an iterator.
+    pub fn scale_size_hint(size_hint: (usize, Option<usize>), n: usize) -> (usize, Option<usize>) {
+        let upper = match (size_hint.1, n) {
+            (_, 0) => Some(0),
+            (Some(upper), n) => upper.checked_mul(n),
+            (None, _) => None,
+        };
+        (size_hint.0.saturating_mul(n), upper)
+    }
+
+    #[cfg(not(feature = "std_async_iter"))]
+    pub fn stream_size_hint<S: Stream + ?Sized>(stream: &S) -> (usize, Option<usize>) {
+        stream.size_hint()
+    }
+
+    #[cfg(feature = "std_async_iter")]
+    pub fn stream_size_hint<S: core::stream::Stream + ?Sized>(
+        stream: &S,
+    ) -> (usize, Option<usize>) {
+        stream.size_hint()
+    }
+
+    impl<G: Generator<StreamCtx>> StreamReturn for AsyncIteratorItem<G> {
+        type Return = G::Return;
+
+        fn take_return(self: Pin<&mut Self>) -> Option<Self::Return> {
+            // SAFETY: `return_value` is not structurally pinned.
+            unsafe { self.get_unchecked_mut() }.return_value.take()
+        }
+    }
+
     // This is used when the yielded type is *not* an `Option` or `Result` and `let x = x?;` is
     // used as a shorthand for `let Some(x) = x else { return; };`.
     #[doc(hidden)]
@@ -189,13 +841,27 @@ pub mod __internal {
             match Try::branch($e) {
                 ControlFlow::Continue(ok) => ok,
                 ControlFlow::Break(err) => {
-                    yield FromResidual::from_residual(err);
+                    yield $crate::__internal::GenYield::Item(FromResidual::from_residual(err));
                     return;
                 }
             }
         }};
     }
 
+    // This is used in iterator items with a `returns` type, where `?` returns early like it does in
+    // regular functions, both in sync and `async` iterator items.
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! gen_try_return {
+        ($e:expr) => {{
+            use core::ops::{ControlFlow, FromResidual, Try};
+            match Try::branch($e) {
+                ControlFlow::Continue(ok) => ok,
+                ControlFlow::Break(err) => return FromResidual::from_residual(err),
+            }
+        }};
+    }
+
     // This is used when the yielded type is *not* an `Option` or `Result` and `let x = x?;` is
     // used as a shorthand for `let Some(x) = x else { return; };`.
     #[doc(hidden)]
@@ -218,18 +884,32 @@ pub mod __internal {
             match Try::branch($e) {
                 ControlFlow::Continue(ok) => ok,
                 ControlFlow::Break(err) => {
-                    yield core::task::Poll::Ready(FromResidual::from_residual(err));
+                    yield core::task::Poll::Ready($crate::__internal::GenYield::Item(
+                        FromResidual::from_residual(err),
+                    ));
                     return;
                 }
             }
         }};
     }
 
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! gen_yield {
+        ($e:expr) => {
+            // This evaluates to the value received when resumed, `()` unless the iterator item
+            // has a `receives` type.
+            yield $crate::__internal::GenYield::Item($e)
+        };
+    }
+
+    // `yield` evaluates to `()`, like it does in sync iterator items, and the `Context` we get when
+    // resumed replaces the one we had before yielding.
     #[doc(hidden)]
     #[macro_export]
     macro_rules! async_gen_yield {
-        ($e:expr) => {{
-            yield core::task::Poll::Ready($e)
+        ($e:expr, $ctx:expr) => {{
+            $ctx = yield core::task::Poll::Ready($crate::__internal::GenYield::Item($e));
         }};
     }
 
@@ -237,19 +917,155 @@ pub mod __internal {
     #[macro_export]
     macro_rules! async_gen_await {
         ($e:expr, $ctx:expr) => {{
-            unsafe {
-                use core::pin::Pin;
-                use core::task::{Context, Poll};
-                let ctx = &mut *($ctx as *mut Context<'_>);
-                let mut e = $e;
-                let mut future = Pin::new_unchecked(&mut e);
-                loop {
-                    match core::future::Future::poll(Pin::as_mut(&mut future), ctx) {
-                        Poll::Ready(x) => break x,
+            use core::pin::Pin;
+            use core::task::Poll;
+            let mut e = $e;
+            // SAFETY: `e` is never moved again, `async` iterator items are `static` generators.
+            let mut future = unsafe { Pin::new_unchecked(&mut e) };
+            loop {
+                match $ctx.poll(Pin::as_mut(&mut future)) {
+                    Poll::Ready(x) => break x,
+                    Poll::Pending => $ctx = yield Poll::Pending,
+                }
+            }
+        }};
+    }
+
+    // `for await #pat in #e #body`: poll the pinned `Stream` until it's exhausted, yielding
+    // `Poll::Pending` whenever it isn't ready.
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! async_gen_for_await {
+        ($($label:lifetime:)? $pat:pat in $e:expr, $ctx:expr => $body:block) => {{
+            use core::pin::Pin;
+            use core::task::Poll;
+            let mut stream = $e;
+            // SAFETY: `stream` is never moved again, `async` iterator items are `static`
+            // generators.
+            let mut stream = unsafe { Pin::new_unchecked(&mut stream) };
+            $($label:)? loop {
+                let next = loop {
+                    match $ctx.poll_next(Pin::as_mut(&mut stream)) {
+                        Poll::Ready(next) => break next,
                         Poll::Pending => $ctx = yield Poll::Pending,
                     }
-                }
+                };
+                let $pat = match next {
+                    Some(item) => item,
+                    None => break,
+                };
+                $body
+            }
+        }};
+    }
+
+    // `yield from #e`: yield every item of the `IntoIterator` `#e`. With `size_hint`, the iterator
+    // item's `size_hint` follows the delegate's after every item, see `delegate_size_hint`.
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! gen_yield_from {
+        (@size_hint $e:expr, $tail:expr) => {{
+            use core::iter::{IntoIterator, Iterator};
+            use $crate::__internal::{delegate_size_hint, GenYield};
+            let mut iter = IntoIterator::into_iter($e);
+            while let Some(item) = Iterator::next(&mut iter) {
+                let size_hint = delegate_size_hint(Iterator::size_hint(&iter), $tail);
+                yield GenYield::SizeHint(size_hint);
+                yield GenYield::Item(item);
             }
         }};
+        ($e:expr) => {{
+            for item in $e {
+                yield $crate::__internal::GenYield::Item(item);
+            }
+        }};
+        ($e:expr, size_hint) => {
+            $crate::gen_yield_from!(@size_hint $e, false)
+        };
+        ($e:expr, size_hint, tail) => {
+            $crate::gen_yield_from!(@size_hint $e, true)
+        };
+    }
+
+    // The `size_hint` of the argument `$e` of an iterator item that a `for` loop in its body
+    // iterates, for the inferred `size_hint` of the iterator item. The probe is referenced once
+    // for every trait, so that method resolution picks the first one that applies: `Iterator`s,
+    // then `Copy` `IntoIterator`s like `&[T]`, then collections whose references are
+    // `IntoIterator`, like `Vec<T>`. Anything else has an unknown size.
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! size_hint_of {
+        ($e:expr) => {{
+            #[allow(unused_imports)]
+            use $crate::__internal::{
+                CopySizeHint, IteratorSizeHint, RefSizeHint, SizeHintProbe, UnknownSizeHint,
+            };
+            (&&&&SizeHintProbe(&$e)).size_hint_of()
+        }};
+    }
+
+    // `set_size_hint!(#e)`: the iterator item's `size_hint` is `#e` from now on, shrinking with
+    // every item it yields.
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! gen_set_size_hint {
+        ($e:expr) => {{
+            yield $crate::__internal::GenYield::SizeHint($e);
+        }};
+    }
+
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! async_gen_set_size_hint {
+        ($e:expr, $ctx:expr) => {{
+            $ctx = yield core::task::Poll::Ready($crate::__internal::GenYield::SizeHint($e));
+        }};
+    }
+
+    // `yield from #e` in an `async` iterator item: yield every item of the `Stream` `#e`, like
+    // `for await item in #e { yield item; }` would.
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! async_gen_yield_from {
+        (@size_hint $e:expr, $ctx:expr, $tail:expr) => {{
+            use core::pin::Pin;
+            use core::task::Poll;
+            use $crate::__internal::{delegate_size_hint, stream_size_hint, GenYield};
+            let mut stream = $e;
+            // SAFETY: `stream` is never moved again, `async` iterator items are `static`
+            // generators.
+            let mut stream = unsafe { Pin::new_unchecked(&mut stream) };
+            loop {
+                let next = loop {
+                    match $ctx.poll_next(Pin::as_mut(&mut stream)) {
+                        Poll::Ready(next) => break next,
+                        Poll::Pending => $ctx = yield Poll::Pending,
+                    }
+                };
+                let item = match next {
+                    Some(item) => item,
+                    None => break,
+                };
+                let size_hint = delegate_size_hint(stream_size_hint(&*stream), $tail);
+                $ctx = yield Poll::Ready(GenYield::SizeHint(size_hint));
+                $ctx = yield Poll::Ready(GenYield::Item(item));
+            }
+        }};
+        ($e:expr, $ctx:expr) => {
+            $crate::async_gen_for_await! {
+                item in $e, $ctx => {
+                    $crate::async_gen_yield!(item, $ctx);
+                }
+            }
+        };
+        ($e:expr, $ctx:expr, size_hint) => {
+            $crate::async_gen_yield_from!(@size_hint $e, $ctx, false)
+        };
+        ($e:expr, $ctx:expr, size_hint, tail) => {
+            $crate::async_gen_yield_from!(@size_hint $e, $ctx, true)
+        };
     }
 }
+
+#[cfg(doctest)]
+mod compile_fail;
@@ -108,3 +108,54 @@ fn test_foo_method() {
     assert_eq!(iter.next(), Some(0));
     assert!(iter.next().is_none());
 }
+
+iterator_item! {
+    /// `where` clauses go after the yielded type.
+    gen fn min_max<I>(iter: I) -> I::Item
+    where
+        I: Iterator,
+        I::Item: Ord + Copy,
+    {
+        let mut iter = iter;
+        let first = iter.next()?;
+        let (mut min, mut max) = (first, first);
+        for n in iter {
+            min = std::cmp::min(min, n);
+            max = std::cmp::max(max, n);
+        }
+        yield min;
+        yield max;
+    }
+}
+
+#[test]
+fn test_where_clause() {
+    assert_eq!(
+        &[1, 9][..],
+        &min_max(vec![3, 1, 9, 4].into_iter()).collect::<Vec<_>>()[..]
+    );
+    assert!(min_max(Vec::<i32>::new().into_iter()).next().is_none());
+}
+
+iterator_item! {
+    syntax = "gen-arrow";
+    /// Only the syntax of RFC 2996 is accepted with this syntax profile.
+    gen fn countdown(from: i32) -> i32 {
+        for n in (0..=from).rev() {
+            yield n;
+        }
+    }
+}
+
+iterator_item! {
+    syntax = "gen-arrow";
+    r#gen fn raw_keyword() -> i32 {
+        yield 1;
+    }
+}
+
+#[test]
+fn test_syntax_profile() {
+    assert_eq!(countdown(2).collect::<Vec<_>>(), vec![2, 1, 0]);
+    assert_eq!(raw_keyword().collect::<Vec<_>>(), vec![1]);
+}
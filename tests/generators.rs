@@ -1,4 +1,5 @@
 #![feature(generators, generator_trait, let_else, try_trait_v2)]
+#![feature(closure_lifetime_binder)]
 use iterator_item::iterator_item;
 
 iterator_item! {
@@ -17,8 +18,10 @@ fn test_foo() {
     assert_eq!(foo.size_hint(), (10, Some(10)));
     for n in 0..10 {
         assert_eq!(foo.next(), Some(n));
+        assert_eq!(foo.size_hint(), (9 - n as usize, Some(9 - n as usize)));
     }
     assert!(foo.next().is_none());
+    assert_eq!(foo.size_hint(), (0, Some(0)));
 }
 
 iterator_item! {
@@ -43,6 +46,165 @@ fn test_bar() {
     assert_eq!(&[42, 1, 2, 3, 42][..], &bar.collect::<Vec<_>>()[..]);
 }
 
+iterator_item! {
+    /// Without `#[size_hint]`, it is inferred from the `yield`s and the arguments' `size_hint`.
+    fn* inferred(names: Vec<&'static str>, iter: impl Iterator<Item = i32>) yields i32 {
+        yield 42;
+        for name in names {
+            let len = name.len() as i32;
+            yield len;
+            yield -len;
+        }
+        for n in iter {
+            yield n;
+        }
+    }
+}
+
+iterator_item! {
+    fn* not_inferred(iter: impl Iterator<Item = i32>) yields i32 {
+        for n in iter {
+            if n > 0 {
+                yield n;
+            }
+        }
+    }
+}
+
+iterator_item! {
+    /// `set_size_hint!` refines the `size_hint` once the body knows more.
+    fn* chunks(data: Vec<u8>, size: usize) yields Vec<u8> {
+        let count = (data.len() + size - 1) / size;
+        set_size_hint!((count, Some(count)));
+        let chunks: Vec<Vec<u8>> = data.chunks(size).map(<[u8]>::to_vec).collect();
+        for chunk in chunks {
+            yield chunk;
+        }
+    }
+}
+
+#[test]
+fn test_set_size_hint() {
+    let mut chunks = chunks(vec![1, 2, 3, 4, 5], 2);
+    assert_eq!(chunks.size_hint(), (0, None));
+    assert_eq!(chunks.next(), Some(vec![1, 2]));
+    assert_eq!(chunks.size_hint(), (2, Some(2)));
+    assert_eq!(chunks.by_ref().count(), 2);
+    assert_eq!(chunks.size_hint(), (0, Some(0)));
+}
+
+iterator_item! {
+    #[size_hint(exact = count)]
+    fn* repeat(value: char, count: usize) yields char {
+        for _ in 0..count {
+            yield value;
+        }
+    }
+}
+
+iterator_item! {
+    /// The inferred `size_hint` of a body that always yields the same number of items is exact.
+    #[exact_size]
+    fn* pair(a: i32, b: i32) yields i32 {
+        yield a;
+        yield b;
+    }
+}
+
+iterator_item! {
+    /// Its `size_hint` promises one more item than it yields.
+    #[size_hint((2, Some(2)))]
+    fn* short() yields i32 {
+        yield 1;
+    }
+}
+
+#[test]
+fn test_exact_size() {
+    let mut repeat = repeat('a', 3);
+    assert_eq!(repeat.len(), 3);
+    assert_eq!(repeat.next(), Some('a'));
+    assert_eq!(repeat.len(), 2);
+    assert_eq!(repeat.collect::<String>(), "aa");
+    assert_eq!(pair(1, 2).len(), 2);
+}
+
+#[test]
+#[cfg_attr(
+    debug_assertions,
+    should_panic(
+        expected = "`short` yielded fewer items than its `size_hint` promised: \
+                             at least 2, but it yielded 1"
+    )
+)]
+fn test_size_hint_contract() {
+    assert_eq!(short().count(), 1);
+}
+
+/// An iterator whose `size_hint` promises more items than it has.
+struct Overpromising(Option<i32>);
+
+impl Iterator for Overpromising {
+    type Item = i32;
+
+    fn next(&mut self) -> Option<i32> {
+        self.0.take()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (3, Some(3))
+    }
+}
+
+iterator_item! {
+    /// Its `size_hint` is inferred from the one of `iter`, which is wrong.
+    fn* forward(iter: Overpromising) yields i32 {
+        for n in iter {
+            yield n;
+        }
+    }
+}
+
+#[test]
+fn test_inferred_size_hint_unchecked() {
+    let forward = forward(Overpromising(Some(1)));
+    assert_eq!(forward.size_hint(), (3, Some(3)));
+    assert_eq!(forward.count(), 1);
+}
+
+iterator_item! {
+    #[no_size_hint]
+    fn* opted_out() yields i32 {
+        yield 1;
+    }
+}
+
+iterator_item! {
+    /// Looping over a borrowed argument is inferred too, but holding the borrow across `yield`s
+    /// needs a `static fn*`.
+    static fn* inferred_borrowed(names: Vec<&'static str>) yields usize {
+        for name in &names {
+            yield name.len();
+        }
+    }
+}
+
+#[test]
+fn test_inferred_size_hint() {
+    let iter = inferred(
+        vec!["a", "bc"],
+        vec![1, 2, 3].into_iter().filter(|n| n % 2 == 1),
+    );
+    // 1 + 2 * 2 for `names`, and up to 3 more from `iter`.
+    assert_eq!(iter.size_hint(), (5, Some(8)));
+    assert_eq!(iter.collect::<Vec<_>>(), vec![42, 1, -1, 2, -2, 1, 3]);
+    assert_eq!(not_inferred(vec![1, -1].into_iter()).size_hint(), (0, None));
+    assert_eq!(opted_out().size_hint(), (0, None));
+    let iter = inferred_borrowed(vec!["a", "bc"]);
+    assert_eq!(iter.size_hint(), (2, Some(2)));
+    assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2]);
+}
+
 iterator_item! {
     fn* result() yields Result<i32, ()> {
         fn bar() -> Result<(), ()> {
@@ -108,3 +270,722 @@ fn test_foo_method() {
     assert_eq!(iter.next(), Some(0));
     assert!(iter.next().is_none());
 }
+
+struct Tokens(Vec<String>);
+
+#[derive(Debug, PartialEq)]
+struct Token<'a>(&'a str);
+
+impl Tokens {
+    iterator_item! {
+        /// Elided lifetimes in the yielded type are the one of `&self`, like in regular methods.
+        fn* iter(&self) yields &str {
+            for token in &self.0 {
+                yield token.as_str();
+            }
+        }
+    }
+
+    iterator_item! {
+        fn* tokens(&self) yields Token<'_> {
+            for token in &self.0 {
+                yield Token(token);
+            }
+        }
+    }
+}
+
+iterator_item! {
+    /// Without `&self`, they are the only lifetime in the arguments.
+    fn* words(text: &str) yields &str {
+        for word in text.split(' ') {
+            yield word;
+        }
+    }
+}
+
+#[test]
+fn test_yield_elision() {
+    let tokens = Tokens(vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(tokens.iter().collect::<Vec<_>>(), vec!["a", "b"]);
+    assert_eq!(tokens.tokens().next(), Some(Token("a")));
+    assert_eq!(words("c d").collect::<Vec<_>>(), vec!["c", "d"]);
+}
+
+iterator_item! {
+    /// The iterator borrows from both arguments, which have unrelated lifetimes.
+    fn* zip_refs(a: &[u8], b: &[u16]) yields (u8, u16) {
+        for (a, b) in a.iter().zip(b) {
+            yield (*a, *b);
+        }
+    }
+}
+
+iterator_item! {
+    /// The trait object can only have one lifetime bound, that both `a` and `b` outlive.
+    #[boxed(dyn)]
+    fn* zip_refs_dyn(a: &[u8], b: &[&str]) yields (u8, usize) {
+        for (a, b) in a.iter().zip(b) {
+            yield (*a, b.len());
+        }
+    }
+}
+
+iterator_item! {
+    fn* lengths(words: &[&str], separator: &str, suffix: &str) yields usize {
+        for word in words {
+            yield word.len() + separator.len();
+        }
+        yield suffix.len();
+    }
+}
+
+impl Tokens {
+    iterator_item! {
+        fn* prefixed<'a>(&'a self, prefix: &str) yields &'a str {
+            for token in &self.0 {
+                if token.starts_with(prefix) {
+                    yield token.as_str();
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_capture_several_lifetimes() {
+    let a = vec![1, 2, 3];
+    let zipped: Vec<_> = {
+        let b = vec![4, 5];
+        zip_refs(&a, &b).collect()
+    };
+    assert_eq!(zipped, vec![(1, 4), (2, 5)]);
+    let zipped: Vec<_> = {
+        let b = vec!["a".to_string(), "bc".to_string()];
+        let b: Vec<&str> = b.iter().map(|b| b.as_str()).collect();
+        zip_refs_dyn(&a, &b).collect()
+    };
+    assert_eq!(zipped, vec![(1, 1), (2, 2)]);
+
+    let words = ["ab".to_string(), "c".to_string()];
+    let words: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+    let suffix = "!!!".to_string();
+    assert_eq!(
+        lengths(&words, ", ", &suffix).collect::<Vec<_>>(),
+        vec![4, 3, 3]
+    );
+
+    let tokens = Tokens(vec!["ab".to_string(), "b".to_string(), "ac".to_string()]);
+    let prefixed: Vec<_> = {
+        let prefix = "a".to_string();
+        tokens.prefixed(&prefix).collect()
+    };
+    assert_eq!(prefixed, vec!["ab", "ac"]);
+}
+
+struct TreeNode {
+    value: u32,
+    children: Vec<std::rc::Rc<TreeNode>>,
+}
+
+impl TreeNode {
+    iterator_item! {
+        fn* values(self: &Self) yields &u32 {
+            yield &self.value;
+            for child in &self.children {
+                yield &child.value;
+            }
+        }
+    }
+
+    iterator_item! {
+        /// Elided lifetimes in the yielded type are the one of `self`, like with `&self`.
+        fn* matching(self: &Self, value: &u32) yields &TreeNode {
+            for child in &self.children {
+                if child.value == *value {
+                    yield &**child;
+                }
+            }
+        }
+    }
+
+    iterator_item! {
+        fn* into_values(self: Box<Self>) yields u32 {
+            yield self.value;
+            for child in self.children {
+                yield child.value;
+            }
+        }
+    }
+
+    iterator_item! {
+        fn* into_nodes(self: std::rc::Rc<Self>) yields std::rc::Rc<TreeNode> {
+            for child in self.children.clone() {
+                yield child;
+            }
+            yield self;
+        }
+    }
+
+    iterator_item! {
+        fn* increment(mut self: std::pin::Pin<&mut Self>) yields &u32 {
+            self.value += 1;
+            yield &self.into_ref().get_ref().value;
+        }
+    }
+}
+
+#[test]
+fn test_self_types() {
+    let leaf = |value| {
+        std::rc::Rc::new(TreeNode {
+            value,
+            children: vec![],
+        })
+    };
+    let mut node = TreeNode {
+        value: 1,
+        children: vec![leaf(2), leaf(3), leaf(2)],
+    };
+    assert_eq!(node.values().collect::<Vec<_>>(), vec![&1, &2, &3, &2]);
+    let two = 2;
+    assert_eq!(node.matching(&two).count(), 2);
+    assert_eq!(std::pin::Pin::new(&mut node).increment().next(), Some(&2));
+
+    let node = std::rc::Rc::new(node);
+    let values: Vec<_> = node.clone().into_nodes().map(|node| node.value).collect();
+    assert_eq!(values, vec![2, 3, 2, 2]);
+    assert_eq!(std::rc::Rc::strong_count(&node), 1);
+
+    let node = Box::new(std::rc::Rc::try_unwrap(node).ok().unwrap());
+    assert_eq!(node.into_values().collect::<Vec<_>>(), vec![2, 2, 3, 2]);
+}
+
+#[test]
+fn test_fused() {
+    let mut foo = foo();
+    for n in 0..10 {
+        assert_eq!(foo.next(), Some(n));
+    }
+    assert!(foo.next().is_none());
+    // Resuming a completed generator would panic, but iterator items are fused.
+    assert!(foo.next().is_none());
+    assert!(foo.next().is_none());
+
+    fn assert_fused<I: std::iter::FusedIterator>(_: &I) {}
+    assert_fused(&foo);
+}
+
+iterator_item! {
+    /// `where` clauses go after the yielded type.
+    gen min_max<I>(iter: I) -> I::Item
+    where
+        I: Iterator,
+        I::Item: Ord + Copy,
+    {
+        let mut iter = iter;
+        let first = iter.next()?;
+        let (mut min, mut max) = (first, first);
+        for n in iter {
+            min = std::cmp::min(min, n);
+            max = std::cmp::max(max, n);
+        }
+        yield min;
+        yield max;
+    }
+}
+
+#[test]
+fn test_where_clause() {
+    assert_eq!(
+        &[1, 9][..],
+        &min_max(vec![3, 1, 9, 4].into_iter()).collect::<Vec<_>>()[..]
+    );
+    assert!(min_max(Vec::<i32>::new().into_iter()).next().is_none());
+}
+
+iterator_item! {
+    /// `return`, `?` and `yield` in closures and nested items aren't desugared.
+    fn* parse_all(input: &str) yields Result<i32, std::num::ParseIntError> {
+        fn parse_pair(a: &str, b: &str) -> Result<i32, std::num::ParseIntError> {
+            Ok(a.parse::<i32>()? + b.parse::<i32>()?)
+        }
+
+        struct Doubler;
+        impl Doubler {
+            fn double(x: &str) -> Option<i32> {
+                Some(x.parse::<i32>().ok()? * 2)
+            }
+        }
+
+        let sum = input
+            .split(',')
+            .map(|x| {
+                if x.is_empty() {
+                    return Ok(0);
+                }
+                Ok::<_, std::num::ParseIntError>(x.parse::<i32>()?)
+            })
+            .collect::<Result<Vec<i32>, _>>()?
+            .into_iter()
+            .sum();
+        yield Ok(sum);
+        yield parse_pair("1", "2");
+        yield Ok(Doubler::double("4").unwrap());
+        yield parse_pair("1", "x");
+    }
+}
+
+#[test]
+fn test_nested_scopes() {
+    let result: Vec<_> = parse_all("1,,2,3").collect();
+    assert_eq!(result[..3], [Ok(6), Ok(3), Ok(8)]);
+    assert!(result[3].is_err());
+    assert_eq!(result.len(), 4);
+
+    let mut result = parse_all("1,a");
+    assert!(result.next().unwrap().is_err());
+    assert!(result.next().is_none());
+}
+
+iterator_item! {
+    /// `?` and `yield` in the arguments of well known macros are desugared too.
+    fn* macro_args(input: Vec<&'static str>) yields Result<Vec<i32>, std::num::ParseIntError> {
+        let first = vec![input[0].parse::<i32>()?];
+        assert!(input[1].parse::<i32>()? > 0, "{:?}", first);
+        assert_eq!(vec![yield Ok(first.clone()); 2], vec![(), ()]);
+        yield Ok(vec![input[2].parse::<i32>()?; 2]);
+        matches!(input[3].parse::<i32>()?, 0..=9);
+        yield Ok(vec![]);
+    }
+}
+
+#[test]
+fn test_macro_args() {
+    let result: Vec<_> = macro_args(vec!["1", "2", "3", "4"]).collect();
+    assert_eq!(result, vec![Ok(vec![1]), Ok(vec![3, 3]), Ok(vec![])]);
+
+    let mut result = macro_args(vec!["1", "x", "3", "4"]);
+    assert!(result.next().unwrap().is_err());
+    assert!(result.next().is_none());
+
+    let mut result = macro_args(vec!["1", "2", "3", "x"]);
+    assert_eq!(result.nth(1), Some(Ok(vec![3, 3])));
+    assert!(result.next().unwrap().is_err());
+    assert!(result.next().is_none());
+}
+
+mod tokens {
+    /// A `vec!` that isn't the one from `std`.
+    macro_rules! stringify_tokens {
+        ($($tokens:tt)*) => {
+            stringify!($($tokens)*)
+        };
+    }
+
+    pub(crate) use stringify_tokens as vec;
+}
+
+iterator_item! {
+    /// The arguments of other macros are left as written, even when they are called like a well
+    /// known one, and even if they contain a `yield`, which they might handle themselves.
+    fn* other_macro_args() yields &'static str {
+        yield tokens::vec![x?];
+        yield stringify!(yield 1);
+    }
+}
+
+#[test]
+fn test_other_macro_args() {
+    let result: Vec<_> = other_macro_args().collect();
+    assert_eq!(result, vec!["x ?", "yield 1"]);
+}
+
+iterator_item! {
+    /// `yield from` yields every item of its delegate, and the delegate's `size_hint` carries
+    /// over to the iterator item.
+    fn* yield_from(a: Vec<i32>, b: Vec<i32>) yields i32 {
+        yield from a;
+        yield 0;
+        yield from b.into_iter().map(|x| x * 10);
+    }
+}
+
+#[test]
+fn test_yield_from() {
+    let mut iter = yield_from(vec![1, 2], vec![3, 4]);
+    assert_eq!(iter.size_hint(), (0, None));
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.size_hint(), (1, None));
+    assert_eq!(iter.next(), Some(2));
+    assert_eq!(iter.size_hint(), (0, None));
+    assert_eq!(iter.next(), Some(0));
+    assert_eq!(iter.next(), Some(30));
+    // Nothing comes after the last `yield from`, so its upper bound is ours too.
+    assert_eq!(iter.size_hint(), (1, Some(1)));
+    assert_eq!(iter.next(), Some(40));
+    assert_eq!(iter.size_hint(), (0, Some(0)));
+    assert_eq!(iter.next(), None);
+}
+
+iterator_item! {
+    /// An explicit `size_hint` is left alone, and `from` can still be used as a binding.
+    #[size_hint((3, Some(3)))]
+    fn* yield_from_explicit(from: i32) yields i32 {
+        yield from;
+        yield from + 1;
+        yield from std::iter::once(from + 2);
+    }
+}
+
+iterator_item! {
+    /// Parentheses and brackets after `from` always delegate, with or without a space. Calling
+    /// `from` needs parentheses around it.
+    fn* yield_from_groups(from: fn(i32) -> i32) yields i32 {
+        yield from (vec![1], 0).0;
+        yield from [2, 3];
+        yield from(vec![4], 0).0;
+        yield (from)(5);
+    }
+}
+
+#[test]
+fn test_yield_from_groups() {
+    let iter = yield_from_groups(|n| n);
+    assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn test_yield_from_explicit() {
+    let mut iter = yield_from_explicit(1);
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.next(), Some(2));
+    assert_eq!(iter.next(), Some(3));
+    assert_eq!(iter.size_hint(), (0, Some(0)));
+    assert_eq!(iter.next(), None);
+}
+
+type ParseResult<T> = Result<T, std::num::ParseIntError>;
+
+iterator_item! {
+    /// Qualified paths to `Result` and `Option` yield the error too.
+    fn* parse_qualified(input: Vec<&'static str>) yields std::result::Result<i32, std::num::ParseIntError> {
+        for x in input {
+            yield Ok(x.parse::<i32>()?);
+        }
+    }
+}
+
+iterator_item! {
+    /// Type aliases aren't recognized, `#[try_yield]` makes `?` yield the error anyway.
+    #[try_yield]
+    fn* parse_aliased(input: Vec<&'static str>) yields ParseResult<i32> {
+        for x in input {
+            yield Ok(x.parse::<i32>()?);
+        }
+    }
+}
+
+iterator_item! {
+    /// `#[try_stop]` makes `?` stop without yielding the error, even for a `Result`.
+    #[try_stop]
+    fn* parse_until_error(input: Vec<&'static str>) yields Result<i32, std::num::ParseIntError> {
+        for x in input {
+            yield Ok(x.parse::<i32>()?);
+        }
+    }
+}
+
+#[test]
+fn test_try_semantics() {
+    let input = vec!["1", "x", "3"];
+    let qualified: Vec<_> = parse_qualified(input.clone()).collect();
+    assert_eq!(qualified.len(), 2);
+    assert!(qualified[1].is_err());
+
+    let aliased: Vec<_> = parse_aliased(input.clone()).collect();
+    assert_eq!(aliased.len(), 2);
+    assert!(aliased[1].is_err());
+
+    let until_error: Vec<_> = parse_until_error(input.clone()).collect();
+    assert_eq!(until_error, vec![Ok(1)]);
+
+    let block: Vec<_> = iterator_item::gen! {
+        #[try_stop]
+        yields Result<i32, std::num::ParseIntError> {
+            for x in input {
+                yield Ok(x.parse::<i32>()?);
+            }
+        }
+    }
+    .collect();
+    assert_eq!(block, vec![Ok(1)]);
+}
+
+iterator_item! {
+    /// `returns` gives the iterator item a value to return after its last `yield`, and makes `?`
+    /// return early like it does in regular functions.
+    fn* sum_parsed(input: Vec<&'static str>) yields i32 returns Result<i32, std::num::ParseIntError> {
+        let mut sum = 0;
+        for x in input {
+            if x.is_empty() {
+                return Ok(sum);
+            }
+            let x = x.parse::<i32>()?;
+            sum += x;
+            yield x;
+        }
+        Ok(sum)
+    }
+}
+
+#[test]
+fn test_returns() {
+    use iterator_item::IteratorReturn;
+
+    let mut iter = sum_parsed(vec!["1", "2", "3"]);
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.take_return(), None);
+    assert_eq!(iter.finish(), Ok(6));
+
+    let mut iter = sum_parsed(vec!["1", "", "3"]);
+    assert_eq!(iter.by_ref().collect::<Vec<_>>(), vec![1]);
+    assert_eq!(iter.take_return(), Some(Ok(1)));
+    assert_eq!(iter.take_return(), None);
+
+    assert!(sum_parsed(vec!["1", "x"]).finish().is_err());
+}
+
+iterator_item! {
+    /// A push parser: it receives chunks of text and yields the lines they complete. An empty
+    /// chunk marks the end of the input, and whatever was left is returned.
+    fn* lines() yields Vec<String> receives mut chunk: &str returns String {
+        let mut partial = String::new();
+        while !chunk.is_empty() {
+            let mut lines = vec![];
+            for c in chunk.chars() {
+                if c == '\n' {
+                    lines.push(std::mem::take(&mut partial));
+                } else {
+                    partial.push(c);
+                }
+            }
+            chunk = yield lines;
+        }
+        partial
+    }
+}
+
+iterator_item! {
+    /// Without a pattern, the value of the first `resume` is dropped.
+    fn* echo() yields u8 receives u8 {
+        let mut last = 0;
+        loop {
+            last = yield last;
+        }
+    }
+}
+
+#[test]
+fn test_receives() {
+    use iterator_item::{Coroutine, CoroutineState};
+
+    // Everything received shares a single lifetime, which has to outlive the coroutine item.
+    let (first, second) = (String::from("a\nb"), String::from("c\nd\n"));
+    let mut lines = lines();
+    assert_eq!(
+        lines.resume(&first),
+        CoroutineState::Yielded(vec!["a".to_string()])
+    );
+    assert_eq!(
+        lines.resume(&second),
+        CoroutineState::Yielded(vec!["bc".to_string(), "d".to_string()]),
+    );
+    assert_eq!(lines.resume("e"), CoroutineState::Yielded(vec![]));
+    assert_eq!(lines.resume(""), CoroutineState::Complete("e".to_string()));
+
+    let mut echo = echo();
+    assert_eq!(echo.resume(1), CoroutineState::Yielded(0));
+    assert_eq!(echo.resume(2), CoroutineState::Yielded(2));
+    assert_eq!(echo.resume(3), CoroutineState::Yielded(3));
+}
+
+iterator_item! {
+    /// `static` iterator items can hold borrows of their own locals across `yield`s.
+    static fn* shouted_words(text: &str) yields String {
+        let text = text.to_uppercase();
+        for word in text.split_whitespace() {
+            yield word.to_string();
+        }
+    }
+}
+
+struct Lines(String);
+
+impl Lines {
+    iterator_item! {
+        #[boxed]
+        fn* numbered(&self) yields (usize, String) {
+            let lines: Vec<&str> = self.0.lines().collect();
+            for (i, line) in lines.iter().enumerate() {
+                yield (i, line.to_string());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_boxed() {
+    let words: Vec<_> = shouted_words("hello  there").collect();
+    assert_eq!(words, vec!["HELLO", "THERE"]);
+
+    let lines = Lines("a\nb".to_string());
+    let mut numbered = lines.numbered();
+    assert_eq!(numbered.next(), Some((0, "a".to_string())));
+    assert_eq!(numbered.next(), Some((1, "b".to_string())));
+    assert!(numbered.next().is_none());
+}
+
+#[test]
+fn test_gen_block() {
+    let prefix = String::from("x");
+    let numbers = vec![1, 2, 3];
+    let iter = iterator_item::gen! {
+        for n in numbers {
+            if n % 2 == 1 {
+                yield format!("{}{}", prefix, n);
+            }
+        }
+    };
+    assert_eq!(iter.collect::<Vec<_>>(), vec!["x1", "x3"]);
+
+    let input = vec!["1", "a", "3"];
+    let mut parsed = iterator_item::gen! {
+        #[size_hint((0, Some(3)))]
+        yields Result<i32, std::num::ParseIntError> {
+            for x in input {
+                yield Ok(x.parse::<i32>()?);
+            }
+        }
+    };
+    assert_eq!(parsed.size_hint(), (0, Some(3)));
+    assert_eq!(parsed.next(), Some(Ok(1)));
+    assert!(parsed.next().unwrap().is_err());
+    assert_eq!(parsed.next(), None);
+}
+
+struct Node {
+    edges: Vec<usize>,
+}
+
+fn reachable<'a>(
+    nodes: &'a [Node],
+    start: usize,
+    neighbors: impl Fn(&'a Node) -> Box<dyn std::iter::FusedIterator<Item = usize> + 'a>,
+) -> Vec<usize> {
+    let mut seen = vec![start];
+    let mut stack = vec![start];
+    while let Some(node) = stack.pop() {
+        for next in neighbors(&nodes[node]) {
+            if !seen.contains(&next) {
+                seen.push(next);
+                stack.push(next);
+            }
+        }
+    }
+    seen
+}
+
+#[test]
+fn test_gen_closure() {
+    let nodes = vec![
+        Node { edges: vec![1, 2] },
+        Node { edges: vec![2] },
+        Node { edges: vec![0] },
+        Node { edges: vec![0] },
+    ];
+    let neighbors = iterator_item::gen!(|node: &Node| yields usize {
+        for e in &node.edges {
+            yield *e;
+        }
+    });
+    assert_eq!(reachable(&nodes, 0, neighbors), vec![0, 1, 2]);
+
+    let step = 10;
+    let counter = iterator_item::gen!(move |from: i32, count: i32| {
+        for i in 0..count {
+            yield from + i * step;
+        }
+    });
+    assert_eq!(counter(1, 3).collect::<Vec<_>>(), vec![1, 11, 21]);
+    assert_eq!(counter(2, 2).collect::<Vec<_>>(), vec![2, 12]);
+
+    // Every call gets its own clone of the captured `Vec`.
+    let names = vec!["a".to_string(), "b".to_string()];
+    let prefixed = iterator_item::gen!(
+        #[clone_captures]
+        move |prefix: char| {
+            for name in names {
+                yield format!("{}{}", prefix, name);
+            }
+        }
+    );
+    assert_eq!(prefixed('x').collect::<Vec<_>>(), vec!["xa", "xb"]);
+    assert_eq!(prefixed('y').collect::<Vec<_>>(), vec!["ya", "yb"]);
+
+    // Without `#[clone_captures]`, the captures don't need to be `Clone`.
+    let (sender, receiver) = std::sync::mpsc::channel();
+    sender.send(1).unwrap();
+    sender.send(2).unwrap();
+    drop(sender);
+    let received = iterator_item::gen!(move |scale: i32| {
+        for n in receiver {
+            yield n * scale;
+        }
+    });
+    assert_eq!(received(10).collect::<Vec<_>>(), vec![10, 20]);
+
+    let chained = iterator_item::gen!(|a: &[i32], b: &[i32]| yields i32 {
+        for n in a.iter().chain(b) {
+            yield *n;
+        }
+    });
+    let a = vec![1, 2];
+    let chained: Vec<_> = {
+        let b = vec![3];
+        chained(&a, &b).collect()
+    };
+    assert_eq!(chained, vec![1, 2, 3]);
+}
+
+iterator_item! {
+    /// `unsafe` iterator items can use unsafe operations in their body.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads of `len` bytes for as long as the iterator is used.
+    unsafe fn* raw_bytes(ptr: *const u8, len: usize) yields u8 {
+        for i in 0..len {
+            yield *ptr.add(i);
+        }
+    }
+}
+
+#[test]
+fn test_unsafe() {
+    let bytes = [1u8, 2, 3];
+    let iter = unsafe { raw_bytes(bytes.as_ptr(), bytes.len()) };
+    assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2, 3]);
+}
+
+iterator_item! {
+    /// `extern "Rust"` is the ABI of every Rust function, it's accepted too.
+    pub extern "Rust" fn* rust_abi() yields i32 {
+        yield 1;
+    }
+}
+
+#[test]
+fn test_rust_abi() {
+    assert_eq!(rust_abi().collect::<Vec<_>>(), vec![1]);
+}
@@ -108,3 +108,48 @@ fn test_foo_method() {
     assert_eq!(iter.next(), Some(0));
     assert!(iter.next().is_none());
 }
+
+iterator_item! {
+    /// `where` clauses go after the yielded type.
+    fn* min_max<I>(iter: I) yields I::Item
+    where
+        I: Iterator,
+        I::Item: Ord + Copy,
+    {
+        let mut iter = iter;
+        let first = iter.next()?;
+        let (mut min, mut max) = (first, first);
+        for n in iter {
+            min = std::cmp::min(min, n);
+            max = std::cmp::max(max, n);
+        }
+        yield min;
+        yield max;
+    }
+}
+
+#[test]
+fn test_where_clause() {
+    assert_eq!(
+        &[1, 9][..],
+        &min_max(vec![3, 1, 9, 4].into_iter()).collect::<Vec<_>>()[..]
+    );
+    assert!(min_max(Vec::<i32>::new().into_iter()).next().is_none());
+}
+
+iterator_item! {
+    /// `where` clauses also work on iterator items that don't yield a value.
+    fn* tick<I>(iter: I)
+    where
+        I: Iterator,
+    {
+        for _ in iter {
+            yield;
+        }
+    }
+}
+
+#[test]
+fn test_where_clause_no_yield_type() {
+    assert_eq!(tick(0..3).count(), 3);
+}
@@ -0,0 +1,110 @@
+#![feature(generators, generator_trait, try_trait_v2)]
+#![feature(return_position_impl_trait_in_trait)]
+#![allow(incomplete_features)]
+
+use iterator_item::iterator;
+
+#[iterator]
+fn evens(limit: u32) -> impl Iterator<Item = u32> {
+    for n in 0..limit {
+        if n % 2 == 0 {
+            yield n;
+        }
+    }
+}
+
+#[test]
+fn test_fn() {
+    assert_eq!(evens(5).collect::<Vec<_>>(), vec![0, 2, 4]);
+}
+
+/// Returning an `ExactSizeIterator` needs a `size_hint` that is exact and known up front.
+#[iterator]
+#[size_hint(exact = items.len())]
+fn doubled(items: Vec<u32>) -> impl ExactSizeIterator<Item = u32> {
+    for item in items {
+        yield item * 2;
+    }
+}
+
+#[test]
+fn test_exact_size() {
+    let mut doubled = doubled(vec![1, 2, 3]);
+    assert_eq!(doubled.len(), 3);
+    assert_eq!(doubled.next(), Some(2));
+    assert_eq!(doubled.len(), 2);
+}
+
+struct Words(String);
+
+impl Words {
+    #[iterator]
+    pub fn lengths(&self) -> impl Iterator<Item = usize> + '_ {
+        for word in self.0.split_whitespace() {
+            yield word.len();
+        }
+    }
+
+    /// `Item` already captures `'a`, the iterator also captures the lifetime of `prefix`.
+    #[iterator]
+    pub fn starting_with<'a>(&'a self, prefix: &str) -> impl Iterator<Item = &'a str> {
+        for word in self.0.split_whitespace() {
+            if word.starts_with(prefix) {
+                yield word;
+            }
+        }
+    }
+}
+
+trait Shape {
+    #[iterator]
+    fn sides(&self) -> impl Iterator<Item = u32>;
+
+    #[iterator]
+    fn perimeter_steps(&self) -> impl Iterator<Item = u32> {
+        let mut total = 0;
+        for side in self.sides() {
+            total += side;
+            yield total;
+        }
+    }
+}
+
+struct Square(u32);
+
+impl Shape for Square {
+    #[iterator]
+    fn sides(&self) -> impl Iterator<Item = u32> {
+        for _ in 0..4 {
+            yield self.0;
+        }
+    }
+}
+
+#[test]
+fn test_methods() {
+    let words = Words("a bb ccc".to_string());
+    assert_eq!(words.lengths().collect::<Vec<_>>(), vec![1, 2, 3]);
+    let prefix = "b".to_string();
+    assert_eq!(words.starting_with(&prefix).collect::<Vec<_>>(), vec!["bb"]);
+    assert_eq!(
+        Square(2).perimeter_steps().collect::<Vec<_>>(),
+        vec![2, 4, 6, 8]
+    );
+}
+
+#[iterator]
+/// The yield type comes from `Item`, so `?` yields the error like it does in `fn*`s.
+fn parse_all(input: &[&str]) -> impl Iterator<Item = Result<i32, std::num::ParseIntError>> {
+    for x in input {
+        yield Ok(x.parse::<i32>()?);
+    }
+}
+
+#[test]
+fn test_try_yield() {
+    let mut parsed = parse_all(&["1", "a", "3"]);
+    assert_eq!(parsed.next(), Some(Ok(1)));
+    assert!(parsed.next().unwrap().is_err());
+    assert_eq!(parsed.next(), None);
+}
@@ -31,6 +31,32 @@ async fn test_foo() {
     assert_eq!(x, 3);
 }
 
+iterator_item::iterator_item! {
+    async fn* countdown<F: Future<Output = usize>>(start: F) yields usize {
+        let start = start.await;
+        set_size_hint!((start, Some(start)));
+        for n in (1..=start).rev() {
+            yield n;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_size_hint() {
+    use futures::Stream;
+    // `foo`'s `size_hint` is inferred from its three `yield`s, and shrinks with every item.
+    let mut foo = Box::pin(foo(async { 1 }));
+    assert_eq!(foo.size_hint(), (3, Some(3)));
+    assert_eq!(foo.next().await, Some(0));
+    assert_eq!(foo.size_hint(), (2, Some(2)));
+    let mut countdown = Box::pin(countdown(async { 3 }));
+    assert_eq!(countdown.size_hint(), (0, None));
+    assert_eq!(countdown.next().await, Some(3));
+    assert_eq!(countdown.size_hint(), (2, Some(2)));
+    assert_eq!(countdown.by_ref().count().await, 2);
+    assert_eq!(countdown.size_hint(), (0, Some(0)));
+}
+
 #[tokio::test]
 async fn test_stream() {
     let mut stream = Box::pin(stream(vec![
@@ -72,3 +98,251 @@ async fn test_result() {
     assert_eq!(result.next().await, Some(Err(())));
     assert!(result.next().await.is_none())
 }
+
+#[tokio::test]
+async fn test_fused() {
+    use futures::stream::FusedStream;
+
+    let mut foo = Box::pin(foo(async { 1 }));
+    assert!(!foo.is_terminated());
+    while foo.next().await.is_some() {}
+    assert!(foo.is_terminated());
+    assert!(foo.next().await.is_none());
+    assert!(foo.next().await.is_none());
+}
+
+iterator_item::iterator_item! {
+    /// `.await` and `?` inside of `async` blocks and closures belong to them.
+    async fn* nested_async<F: Future<Output = i32>>(fut: F) yields Option<i32> {
+        let inner = async move {
+            let x = fut.await;
+            let y: Option<i32> = Some(x);
+            Some(y? + 1)
+        };
+        let parse = |x: &str| -> Option<i32> { x.parse().ok() };
+        yield inner.await;
+        yield parse("3");
+    }
+}
+
+#[tokio::test]
+async fn test_nested_async() {
+    let result: Vec<_> = nested_async(async { 1 }).collect().await;
+    assert_eq!(result, vec![Some(2), Some(3)]);
+}
+
+iterator_item::iterator_item! {
+    /// `.await` in the arguments of well known macros is desugared too.
+    async fn* macro_args<F: Future<Output = i32>>(fut: F) yields String {
+        let mut fut = Box::pin(fut);
+        assert_eq!(fut.as_mut().await, 1);
+        yield format!("{}", async { 2 }.await);
+        let v = vec![async { 3 }.await; 2];
+        yield format!("{:?}", v);
+    }
+}
+
+#[tokio::test]
+async fn test_macro_args() {
+    let result: Vec<_> = macro_args(async { 1 }).collect().await;
+    assert_eq!(result, vec!["2".to_string(), "[3, 3]".to_string()]);
+}
+
+iterator_item::iterator_item! {
+    /// `join!` and `select!` expand to an `.await`, so they get their own desugaring.
+    async fn* join_select() yields i32 {
+        let (a, b) = futures::join!(async { 1 }, async { 2 });
+        yield a;
+        yield b;
+        let (c, d) = tokio::join!(async { 3 }, async { 4 });
+        yield c + d;
+
+        let mut a = futures::future::ready(10);
+        let mut b = futures::future::pending::<i32>();
+        loop {
+            futures::select! {
+                x = a => yield x,
+                _ = b => unreachable!(),
+                complete => break,
+            }
+        }
+
+        let mut count = 0;
+        loop {
+            tokio::select! {
+                biased;
+                x = async { 20 }, if count < 2 => {
+                    count += 1;
+                    yield x + count;
+                }
+                else => break,
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_join_select() {
+    let result: Vec<_> = join_select().collect().await;
+    assert_eq!(result, vec![1, 2, 7, 10, 21, 22]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_send() {
+    fn assert_send<T: Send>(t: T) -> T {
+        t
+    }
+
+    let futures = vec![1, 2, 3]
+        .into_iter()
+        .map(futures::future::ready)
+        .collect();
+    let stream = assert_send(stream(futures));
+    let handle = tokio::spawn(async move {
+        let mut stream = Box::pin(stream);
+        let mut sum = 0;
+        while let Some(i) = stream.next().await {
+            tokio::task::yield_now().await;
+            sum += i;
+        }
+        sum
+    });
+    assert_eq!(handle.await.unwrap(), 6);
+
+    let handle = tokio::spawn(join_select().collect::<Vec<_>>());
+    assert_eq!(handle.await.unwrap(), vec![1, 2, 7, 10, 21, 22]);
+}
+
+iterator_item::iterator_item! {
+    /// `for await` loops poll the `Stream` with the iterator item's `Context`.
+    async fn* for_await(
+        streams: Vec<Pin<Box<dyn futures::Stream<Item = i32>>>>,
+    ) yields i32 {
+        let futures = vec![1, 2, 3].into_iter().map(futures::future::ready).collect();
+        'outer: for await x in stream(futures) {
+            if x == 2 {
+                continue 'outer;
+            }
+            yield x;
+        }
+        for stream in streams {
+            'inner: for await (i, x) in stream.enumerate() {
+                if x < 0 {
+                    break 'inner;
+                }
+                for await y in futures::stream::iter(0..i) {
+                    if y > 0 {
+                        continue 'inner;
+                    }
+                    yield -1;
+                }
+                yield x;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_for_await() {
+    let streams: Vec<Pin<Box<dyn futures::Stream<Item = i32>>>> = vec![
+        Box::pin(futures::stream::iter(vec![10, 11, 12])),
+        Box::pin(futures::stream::iter(vec![20, -1, 22])),
+    ];
+    let result: Vec<_> = for_await(streams).collect().await;
+    assert_eq!(result, vec![1, 3, 10, -1, 11, -1, 20]);
+}
+
+iterator_item::iterator_item! {
+    /// `yield from` forwards every item of a `Stream`, and its `size_hint`.
+    async fn* yield_from(streams: Vec<Vec<i32>>) yields i32 {
+        for items in streams {
+            yield from futures::stream::iter(items);
+        }
+        yield from stream(vec![futures::future::ready(7), futures::future::ready(8)]);
+        yield from futures::stream::iter(vec![9, 10]);
+    }
+}
+
+#[tokio::test]
+async fn test_yield_from() {
+    use futures::Stream;
+    let mut stream = Box::pin(yield_from(vec![vec![1, 2], vec![], vec![3]]));
+    assert_eq!(stream.size_hint(), (0, None));
+    assert_eq!(stream.next().await, Some(1));
+    assert_eq!(stream.size_hint(), (1, None));
+    assert_eq!(stream.next().await, Some(2));
+    assert_eq!(stream.next().await, Some(3));
+    assert_eq!(stream.next().await, Some(7));
+    assert_eq!(stream.next().await, Some(8));
+    assert_eq!(stream.next().await, Some(9));
+    assert_eq!(stream.size_hint(), (1, Some(1)));
+    assert_eq!(stream.next().await, Some(10));
+    assert_eq!(stream.next().await, None);
+}
+
+iterator_item::iterator_item! {
+    async fn* count<F: Future<Output = i32>>(futures: Vec<F>) yields i32 returns usize {
+        let mut count = 0;
+        for fut in futures {
+            yield fut.await;
+            count += 1;
+        }
+        count
+    }
+}
+
+#[tokio::test]
+async fn test_returns() {
+    use iterator_item::StreamReturn;
+
+    let futures = vec![1, 2].into_iter().map(futures::future::ready).collect();
+    let mut stream = Box::pin(count(futures));
+    assert_eq!(stream.as_mut().take_return(), None);
+    assert_eq!(stream.as_mut().collect::<Vec<_>>().await, vec![1, 2]);
+    assert_eq!(stream.as_mut().take_return(), Some(2));
+}
+
+iterator_item::iterator_item! {
+    /// Boxed `async` iterator items are `Unpin`.
+    #[boxed]
+    async fn* boxed(text: String) yields String {
+        let words: Vec<&str> = text.split(' ').collect();
+        for word in words {
+            yield futures::future::ready(word.to_string()).await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_boxed() {
+    let mut stream = boxed("a b".to_string());
+    assert_eq!(stream.next().await.as_deref(), Some("a"));
+    assert_eq!(stream.next().await.as_deref(), Some("b"));
+    assert_eq!(stream.next().await, None);
+}
+
+#[tokio::test]
+async fn test_gen_block() {
+    let futures = vec![futures::future::ready(1), futures::future::ready(2)];
+    let mut stream = Box::pin(iterator_item::gen! {
+        async {
+            for future in futures {
+                yield future.await * 10;
+            }
+        }
+    });
+    assert_eq!(stream.next().await, Some(10));
+    assert_eq!(stream.next().await, Some(20));
+    assert_eq!(stream.next().await, None);
+}
+
+#[tokio::test]
+async fn test_gen_closure() {
+    let doubled = iterator_item::gen!(async |values: Vec<i32>| {
+        for value in values {
+            yield futures::future::ready(value * 2).await;
+        }
+    });
+    let stream = Box::pin(doubled(vec![1, 2]));
+    assert_eq!(stream.collect::<Vec<_>>().await, vec![2, 4]);
+}
@@ -0,0 +1,179 @@
+#![feature(generators, generator_trait, try_trait_v2)]
+#![feature(return_position_impl_trait_in_trait, min_specialization)]
+#![allow(incomplete_features)]
+
+use futures::stream::StreamExt;
+use iterator_item::iterator_item;
+
+struct Node {
+    value: i32,
+    children: Vec<Node>,
+}
+
+fn tree() -> Node {
+    let leaf = |value| Node {
+        value,
+        children: vec![],
+    };
+    Node {
+        value: 1,
+        children: vec![
+            Node {
+                value: 2,
+                children: vec![leaf(3), leaf(4)],
+            },
+            leaf(5),
+        ],
+    }
+}
+
+trait Tree {
+    iterator_item! {
+        /// Trait methods can be declared without a body.
+        fn* walk(&self) yields &Node;
+    }
+
+    iterator_item! {
+        /// And they can have a default body.
+        fn* values(&self) yields i32 {
+            for node in self.walk() {
+                yield node.value;
+            }
+        }
+    }
+}
+
+impl Tree for Node {
+    iterator_item! {
+        fn* walk(&self) yields &Node {
+            let mut stack = vec![self];
+            while let Some(node) = stack.pop() {
+                stack.extend(node.children.iter().rev());
+                yield node;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_trait() {
+    let tree = tree();
+    assert_eq!(Tree::values(&tree).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+}
+
+/// `#[boxed(dyn)]` returns trait objects, so the trait is dyn compatible, and the iterator items
+/// can be recursive.
+trait DynTree {
+    iterator_item! {
+        #[boxed(dyn)]
+        fn* walk(&self) yields &Node;
+    }
+
+    iterator_item! {
+        #[boxed(dyn)]
+        fn* leaves(&self) yields i32;
+    }
+
+    iterator_item! {
+        #[boxed(dyn)]
+        fn* matching(&self, values: &[i32]) yields i32;
+    }
+
+    iterator_item! {
+        #[boxed(dyn + Send)]
+        async fn* values(&self) yields i32;
+    }
+}
+
+impl DynTree for Node {
+    iterator_item! {
+        #[boxed(dyn)]
+        fn* walk(&self) yields &Node {
+            yield self;
+            for child in &self.children {
+                yield from DynTree::walk(child);
+            }
+        }
+    }
+
+    iterator_item! {
+        // Like `#[boxed]`, this can hold borrows of its locals across `yield`s.
+        #[boxed(dyn)]
+        fn* leaves(&self) yields i32 {
+            let nodes: Vec<&Node> = DynTree::walk(self).collect();
+            for node in &nodes {
+                if node.children.is_empty() {
+                    yield node.value;
+                }
+            }
+        }
+    }
+
+    iterator_item! {
+        // The trait object borrows from both `self` and `values`.
+        #[boxed(dyn)]
+        fn* matching(&self, values: &[i32]) yields i32 {
+            for node in DynTree::walk(self) {
+                if values.contains(&node.value) {
+                    yield node.value;
+                }
+            }
+        }
+    }
+
+    iterator_item! {
+        #[boxed(dyn + Send)]
+        async fn* values(&self) yields i32 {
+            let mut stack = vec![self];
+            while let Some(node) = stack.pop() {
+                stack.extend(node.children.iter().rev());
+                yield futures::future::ready(node.value).await;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_dyn_trait() {
+    let tree = tree();
+    let tree: &dyn DynTree = &tree;
+    let values: Vec<_> = tree.walk().map(|node| node.value).collect();
+    assert_eq!(values, vec![1, 2, 3, 4, 5]);
+    assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![3, 4, 5]);
+    let values = vec![5, 2, 7];
+    assert_eq!(tree.matching(&values).collect::<Vec<_>>(), vec![2, 5]);
+    assert_eq!(tree.values().collect::<Vec<_>>().await, vec![1, 2, 3, 4, 5]);
+}
+
+trait Describe {
+    iterator_item! {
+        #[boxed(dyn)]
+        fn* words(&self) yields &'static str;
+    }
+}
+
+impl<T> Describe for T {
+    iterator_item! {
+        /// `default` iterator items can be specialized.
+        #[boxed(dyn)]
+        default fn* words(&self) yields &'static str {
+            yield "something";
+        }
+    }
+}
+
+impl Describe for i32 {
+    iterator_item! {
+        #[boxed(dyn)]
+        fn* words(&self) yields &'static str {
+            yield "a";
+            yield "number";
+        }
+    }
+}
+
+#[test]
+fn test_specialization() {
+    assert_eq!("a".words().collect::<Vec<_>>(), vec!["something"]);
+    assert_eq!(1.words().collect::<Vec<_>>(), vec!["a", "number"]);
+}